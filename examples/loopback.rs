@@ -1,9 +1,8 @@
 // C:\...\sonar\examples\loopback.rs
 
 use std::error::Error;
-use std::io::Write;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use dev_utils::{
@@ -14,13 +13,15 @@ use dev_utils::{
 };
 use sonar::audio::{self, capture::AudioCapture, playback::AudioPlayback};
 use sonar::modem::fsk::FSK;
-use sonar::stack::datalink::{CodecTrait, SonarCodec, SonarCodecConfig};
+use sonar::stack::datalink::{AsyncPpp, CodecTrait, PppCodec, SonarCodec, SonarCodecConfig};
 
 // --- Constants ---
 const BAUD_RATE: u32 = 300;
 // Note: Confidence is not just SNR anymore. It's scaled by signal strength.
 // A higher value may be needed. Start with a low value like 10.0 and tune up.
 const CONFIDENCE_THRESHOLD: f32 = 4.0;
+// Mean |LLR| a frame's bits need on top: 4 makes every bit about 98% sure.
+const MIN_MEAN_LLR: f32 = 4.0;
 const FREQ_SPACE: f32 = 1200.0; // Bit '0'
 const FREQ_MARK: f32 = 2400.0;  // Bit '1'
 
@@ -54,12 +55,11 @@ fn run_sender() -> Result<(), Box<dyn Error>> {
     info!("Using sample rate: {} Hz", sample_rate);
 
     let fsk_modem = Box::new(FSK::new(sample_rate, FREQ_SPACE, FREQ_MARK, sample_rate / BAUD_RATE));
-    let codec_config = SonarCodecConfig { sample_rate, baud_rate: BAUD_RATE, confidence_threshold: CONFIDENCE_THRESHOLD };
-    let codec = SonarCodec::new(fsk_modem, codec_config);
+    let codec_config = SonarCodecConfig { sample_rate, baud_rate: BAUD_RATE, confidence_threshold: CONFIDENCE_THRESHOLD, min_mean_llr: MIN_MEAN_LLR, ..Default::default() };
+    let codec = PppCodec::new(SonarCodec::new(fsk_modem, codec_config), AsyncPpp::default());
     let playback = AudioPlayback::new_with_device(device)?;
 
-    let mut message = read_input::<String>(Some("Enter message (press Enter to transmit): "))?;
-    message.push('\n');
+    let message = read_input::<String>(Some("Enter message (press Enter to transmit): "))?;
     info!("Preparing to send message...");
 
     let audio_samples = codec.encode(message.as_bytes())?;
@@ -84,50 +84,34 @@ fn run_listener() -> Result<(), Box<dyn Error>> {
     info!("Using sample rate: {} Hz", sample_rate);
 
     let fsk_modem = Box::new(FSK::new(sample_rate, FREQ_SPACE, FREQ_MARK, sample_rate / BAUD_RATE));
-    let codec_config = SonarCodecConfig { sample_rate, baud_rate: BAUD_RATE, confidence_threshold: CONFIDENCE_THRESHOLD };
-    let mut codec = SonarCodec::new(fsk_modem, codec_config);
+    let codec_config = SonarCodecConfig { sample_rate, baud_rate: BAUD_RATE, confidence_threshold: CONFIDENCE_THRESHOLD, min_mean_llr: MIN_MEAN_LLR, ..Default::default() };
+    let mut codec = PppCodec::new(SonarCodec::new(fsk_modem, codec_config), AsyncPpp::default());
     let capture = AudioCapture::new_with_device(device)?;
 
     let stream = capture.start_listening(&config)?;
     stream.play()?;
     info!("Listening for incoming signals... Press Ctrl+C to stop.");
-    info!("Using confidence threshold: {}", CONFIDENCE_THRESHOLD);
-
-    let mut message_buffer: Vec<u8> = Vec::new();
-    let mut last_char_time = Instant::now();
-    const RECEPTION_TIMEOUT: Duration = Duration::from_secs(2);
+    info!("Using confidence threshold: {}, mean |LLR| of at least {}", CONFIDENCE_THRESHOLD, MIN_MEAN_LLR);
 
+    // No reception timeout: the codec drops a signal that stops sending frames, and the
+    // next frame's flag discards whatever was left of the last one
     loop {
-        if last_char_time.elapsed() > RECEPTION_TIMEOUT {
-            codec.reset_state();
-        }
-
         let samples = capture.get_samples();
         if samples.is_empty() {
             thread::sleep(Duration::from_millis(10));
             continue;
         }
 
-        if let Ok(Some(bytes)) = codec.decode(&samples) {
-            last_char_time = Instant::now();
-            for byte in bytes {
-                if byte == b'\n' {
-                    if !message_buffer.is_empty() {
-                        if let Ok(message) = String::from_utf8(message_buffer.clone()) {
-                            println!();
-                            info!("{}", "--- MESSAGE RECEIVED ---".style(Style::Bold).color(dev_utils::format::GREEN));
-                            println!("{}", message);
-                            println!();
-                        }
-                    }
-                    message_buffer.clear();
-                } else {
-                    message_buffer.push(byte);
-                    let char_to_print = (byte as char).to_string();
-                    print!("{}", char_to_print.color(dev_utils::format::CYAN));
-                    std::io::stdout().flush()?;
-                }
+        // Every frame is one message, whatever bytes it holds
+        if let Ok(frames) = codec.decode_frames(&samples) {
+            for message in frames {
+                println!();
+                info!("{}", "--- MESSAGE RECEIVED ---".style(Style::Bold).color(dev_utils::format::GREEN));
+                println!("{}", String::from_utf8_lossy(&message));
+                let stats = codec.codec().stats();
+                info!("{} frames, {} resyncs, clock offset {:+.1} ppm", stats.frames, stats.resyncs, stats.clock_offset_ppm);
+                println!();
             }
         }
     }
-}
\ No newline at end of file
+}
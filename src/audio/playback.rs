@@ -1,7 +1,9 @@
 // src/audio/playback.rs
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use std::{error::Error, sync::Arc};
+use std::{error::Error, sync::{Arc, mpsc::{Receiver, TryRecvError}}};
+
+use crate::modem::StreamModulator;
 
 pub struct AudioPlayback {
     pub config: cpal::StreamConfig, // Device configuration
@@ -77,6 +79,54 @@ impl AudioPlayback {
         Ok(stream)
     }
 
+    /// Plays a stream modulator's signal, pulling bits from `bits` as the device asks for audio.
+    ///
+    /// Nothing is modulated ahead of time: every output callback fills its buffer straight
+    /// from the modulator, so the transmission lasts as long as bits keep coming, in constant
+    /// memory. While the channel has no bits to give the output is silent, and the signal
+    /// resumes where it stopped once more arrive. A symbol only starts once all its bits are
+    /// in; the last one is padded with 0s when the sender hangs up.
+    pub fn transmit_stream(
+        &self,
+        config: &cpal::StreamConfig,
+        mut modulator: Box<dyn StreamModulator + Send>,
+        bits: Receiver<bool>,
+    ) -> Result<cpal::Stream, Box<dyn Error>> {
+        let channels = config.channels as usize;
+        let mut mono = [0.0f32; 512];
+
+        let stream = self.device.build_output_stream(
+            config,
+            move |data: &mut [f32], _: &_| {
+                for frames in data.chunks_mut(mono.len() * channels) {
+                    let mono = &mut mono[..frames.len() / channels];
+                    let mut hung_up = false;
+                    let mut received = std::iter::from_fn(|| match bits.try_recv() {
+                        Ok(bit) => Some(bit),
+                        Err(TryRecvError::Empty) => None,
+                        Err(TryRecvError::Disconnected) => {
+                            hung_up = true;
+                            None
+                        }
+                    });
+                    let mut written = modulator.fill(&mut received, mono);
+                    if hung_up {
+                        modulator.finish();
+                        written += modulator.fill(&mut std::iter::empty(), &mut mono[written..]);
+                    }
+                    mono[written..].fill(0.0);
+                    // Write the same sample to all channels
+                    for (frame, &sample) in frames.chunks_mut(channels).zip(mono.iter()) {
+                        frame.fill(sample);
+                    }
+                }
+            },
+            |err| eprintln!("Error in output stream: {}", err),
+            None,
+        )?;
+        Ok(stream)
+    }
+
     // Private helper method to build the cpal output stream.
     fn build_output_stream(
         &self,
@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::f32::consts::PI;
+
+use dev_utils::warn;
+
+use super::{FSK, MFSK, goertzel};
+
+/// Frequency band the tone modems transmit in.
+///
+/// The audible band is what every speaker and microphone handles well. The near-ultrasonic
+/// band (17-20 kHz) is inaudible to most adults, but needs a sample rate of at least 44.1 kHz
+/// and hardware that still responds up there; laptop speakers often roll off well before.
+/// Use [`BandProfile::probe`] and [`BandProfile::select`] to fall back when it does not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BandProfile {
+    #[default]
+    Audible,
+    NearUltrasonic,
+}
+
+impl BandProfile {
+    /// Lowest acceptable probe response of the near-ultrasonic band, relative to the audible one (-15 dB).
+    pub const MIN_PROBE_RESPONSE: f32 = 0.03;
+
+    /// Audible reference tone of the probe in Hz.
+    const PROBE_AUDIBLE_FREQ: f32 = 1_500.0;
+    /// Near-ultrasonic tone of the probe in Hz, in the middle of the band.
+    const PROBE_ULTRASONIC_FREQ: f32 = 18_500.0;
+
+    /// Usable band `(low, high)` in Hz.
+    pub fn band(self) -> (f32, f32) {
+        match self {
+            BandProfile::Audible => (1_000.0, 3_000.0),
+            BandProfile::NearUltrasonic => (17_000.0, 20_000.0),
+        }
+    }
+
+    /// Checks that the band fits below the Nyquist frequency of `sample_rate`.
+    ///
+    /// A 5% margin is kept, since anti-aliasing filters start rolling off before Nyquist.
+    pub fn check(self, sample_rate: u32) -> Result<(), Box<dyn Error>> {
+        let (_, high) = self.band();
+        let usable = sample_rate as f32 / 2.0 * 0.95;
+        match high <= usable {
+            true => Ok(()),
+            false => Err(format!(
+                "{self:?} band reaches {high} Hz, but a {sample_rate} Hz sample rate only carries up to {usable:.0} Hz"
+            ).into()),
+        }
+    }
+
+    /// FSK modem for this band: 1200/2400 Hz when audible, 18000/19200 Hz near-ultrasonic.
+    pub fn fsk(self, sample_rate: u32, samples_per_bit: u32) -> Result<FSK, Box<dyn Error>> {
+        self.check(sample_rate)?;
+        let (space, mark) = match self {
+            BandProfile::Audible => (1_200.0, 2_400.0),
+            BandProfile::NearUltrasonic => (18_000.0, 19_200.0),
+        };
+        Ok(FSK::new(sample_rate, space, mark, samples_per_bit))
+    }
+
+    /// MFSK modem for this band, with tones spaced by the symbol rate from the bottom of the band.
+    pub fn mfsk(self, sample_rate: u32, tones: usize, samples_per_symbol: u32) -> Result<MFSK, Box<dyn Error>> {
+        self.check(sample_rate)?;
+        let (low, high) = self.band();
+        let spacing = sample_rate as f32 / samples_per_symbol as f32;
+        if low + spacing * (tones - 1) as f32 > high {
+            return Err(format!("{tones} tones {spacing} Hz apart do not fit in the {self:?} band").into());
+        }
+        Ok(MFSK::new(sample_rate, low, spacing, tones, samples_per_symbol))
+    }
+
+    /// Probe signal: an audible reference tone, then a near-ultrasonic one, 200 ms each.
+    ///
+    /// Play it and record it back through the devices that will carry the transfer,
+    /// then pass the recording to [`BandProfile::select`].
+    pub fn probe(sample_rate: u32) -> Vec<f32> {
+        let len = sample_rate as usize / 5;
+        let mut signal = Vec::with_capacity(2 * len);
+        for freq in [Self::PROBE_AUDIBLE_FREQ, Self::PROBE_ULTRASONIC_FREQ] {
+            let omega = 2.0 * PI * freq / sample_rate as f32;
+            let mut tone: Vec<f32> = (0..len).map(|n| 0.5 * (omega * n as f32).sin()).collect();
+            apply_fade(&mut tone, len / 10);
+            signal.extend(tone);
+        }
+        signal
+    }
+
+    /// Near-ultrasonic level of a recorded probe relative to its audible reference.
+    ///
+    /// Energies are summed over 20 ms blocks, which keeps the measurement tolerant of the
+    /// small clock offset between the playing and the recording sound card.
+    pub fn probe_response(recorded: &[f32], sample_rate: u32) -> f32 {
+        let block = (sample_rate / 50) as usize;
+        let (audible, ultrasonic) = recorded.chunks(block).fold((0.0, 0.0), |(a, u), chunk| {
+            (
+                a + goertzel(chunk, Self::PROBE_AUDIBLE_FREQ, sample_rate),
+                u + goertzel(chunk, Self::PROBE_ULTRASONIC_FREQ, sample_rate),
+            )
+        });
+        ultrasonic / (audible + f32::EPSILON)
+    }
+
+    /// Returns this profile if the devices can carry it, or falls back to the audible band.
+    ///
+    /// The near-ultrasonic band is dropped when the sample rate cannot carry it, or when its
+    /// response in the recorded probe is below [`BandProfile::MIN_PROBE_RESPONSE`].
+    pub fn select(self, recorded_probe: &[f32], sample_rate: u32) -> BandProfile {
+        if self == BandProfile::Audible {
+            return self;
+        }
+        if let Err(e) = self.check(sample_rate) {
+            warn!("Falling back to the audible band: {}", e);
+            return BandProfile::Audible;
+        }
+        let response = Self::probe_response(recorded_probe, sample_rate);
+        if response < Self::MIN_PROBE_RESPONSE {
+            warn!("Falling back to the audible band: near-ultrasonic response is only {:.1} dB", 10.0 * response.log10());
+            return BandProfile::Audible;
+        }
+        self
+    }
+}
+
+/// Fades the first and last `fade_len` samples in and out with a raised-cosine envelope.
+///
+/// A tone that starts or stops abruptly clicks, and the click is audible (and spreads out of
+/// band) even when the tone itself is not.
+pub fn apply_fade(signal: &mut [f32], fade_len: usize) {
+    let fade_len = fade_len.min(signal.len() / 2);
+    let len = signal.len();
+    for n in 0..fade_len {
+        let gain = 0.5 - 0.5 * (PI * n as f32 / fade_len as f32).cos();
+        signal[n] *= gain;
+        signal[len - 1 - n] *= gain;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_ultrasonic_checks_nyquist_and_falls_back() {
+        assert!(BandProfile::NearUltrasonic.fsk(44_100, 147).is_ok());
+        assert!(BandProfile::NearUltrasonic.fsk(32_000, 107).is_err());
+
+        let probe = BandProfile::probe(48_000);
+        assert_eq!(BandProfile::NearUltrasonic.select(&probe, 48_000), BandProfile::NearUltrasonic);
+
+        // A speaker that rolls off above 10 kHz: only the audible half comes back
+        let muffled: Vec<f32> = probe.iter().enumerate().map(|(n, &s)| if n < probe.len() / 2 { s } else { 0.01 * s }).collect();
+        assert_eq!(BandProfile::NearUltrasonic.select(&muffled, 48_000), BandProfile::Audible);
+    }
+}
@@ -0,0 +1,230 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use super::{ModemTrait, SAMPLE_RATE, bit_llrs, gray_decode, gray_encode, symbol_bit_energies};
+
+/// CSS (Chirp Spread Spectrum) modem implementation, in the style of LoRa.
+///
+/// Every symbol is a linear up-chirp sweeping `bandwidth` Hz upwards from `low_freq`.
+/// A spreading factor `SF` splits the sweep into `2^SF` chips, and the symbol value is
+/// the number of chips the chirp is cyclically shifted by, so each symbol carries `SF`
+/// bits. Values are Gray coded onto the shifts, so an off-by-one shift costs one bit.
+///
+/// The receiver multiplies a symbol by the conjugate of the unshifted chirp (dechirp),
+/// which turns any shift into a pure tone, and finds it with an FFT. The whole symbol's
+/// energy collapses into a single bin while noise stays spread over all of them: every
+/// extra spreading factor step doubles the symbol length and buys 3 dB, so symbols can
+/// be found well below 0 dB SNR, at the cost of rate.
+///
+/// A timing error of whole chips looks exactly like a different shift. When the codec
+/// primes an all-mark symbol, its measured shift is taken as the reference and later
+/// symbols are read relative to it, which removes that ambiguity.
+pub struct CSS {
+    sample_rate: u32,                 // Sampling rate in Hz
+    low_freq: f32,                    // Start of the sweep in Hz
+    spreading_factor: u32,            // Bits per symbol; the chirp has 2^SF chips
+    samples_per_chip: usize,          // Number of samples per chip
+    dechirp: Vec<Complex<f32>>,       // Conjugate of the unshifted chirp, one symbol long
+    fft: Arc<dyn Fft<f32>>,           // Forward FFT over one symbol
+    reference: Cell<Option<usize>>,   // Chip offset measured on the last primed all-mark symbol
+}
+
+impl fmt::Debug for CSS {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CSS")
+            .field("sample_rate", &self.sample_rate)
+            .field("low_freq", &self.low_freq)
+            .field("bandwidth", &self.bandwidth())
+            .field("spreading_factor", &self.spreading_factor)
+            .finish()
+    }
+}
+
+impl Default for CSS {
+    /// SF7 over 1500-3420 Hz: 128 chips of 25 samples, i.e. 15 symbols or 105 bits per second.
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE, 1_500.0, 1_920.0, 7)
+    }
+}
+
+impl CSS {
+    /// Creates a new CSS modem.
+    ///
+    /// The chip rate (and thus the bandwidth) is rounded to a whole number of samples
+    /// per chip, and `low_freq` to a multiple of the FFT bin spacing, which keeps the
+    /// wrapped part of a shifted chirp coherent with the rest of it.
+    ///
+    /// # Panics
+    /// If `spreading_factor` is outside `5..=12` or the sweep reaches the Nyquist frequency.
+    pub fn new(sample_rate: u32, low_freq: f32, bandwidth: f32, spreading_factor: u32) -> Self {
+        assert!(
+            (5..=12).contains(&spreading_factor),
+            "CSS supports spreading factors 5 to 12, got {spreading_factor}"
+        );
+        assert!(
+            low_freq + bandwidth < sample_rate as f32 / 2.0,
+            "CSS sweep must stay below the Nyquist frequency ({} Hz)",
+            sample_rate / 2
+        );
+        let samples_per_chip = (sample_rate as f32 / bandwidth).round().max(2.0) as usize;
+        let chips = 1usize << spreading_factor;
+        let bin_width = sample_rate as f32 / (samples_per_chip * chips) as f32;
+
+        let mut modem = Self {
+            sample_rate,
+            low_freq: (low_freq / bin_width).round() * bin_width,
+            spreading_factor,
+            samples_per_chip,
+            dechirp: Vec::new(),
+            fft: FftPlanner::new().plan_fft_forward(samples_per_chip * chips),
+            reference: Cell::new(None),
+        };
+        modem.dechirp = (0..modem.samples_per_symbol())
+            .map(|n| Complex::from_polar(1.0, -modem.chirp_phase(n)))
+            .collect();
+        modem
+    }
+
+    /// Number of chips in one symbol (`2^SF`).
+    pub fn chips(&self) -> usize {
+        1 << self.spreading_factor
+    }
+
+    /// Width of the sweep in Hz (the chip rate).
+    pub fn bandwidth(&self) -> f32 {
+        self.sample_rate as f32 / self.samples_per_chip as f32
+    }
+
+    /// Number of samples in one symbol.
+    pub fn samples_per_symbol(&self) -> usize {
+        self.chips() * self.samples_per_chip
+    }
+
+    /// Symbol rate in symbols per second.
+    pub fn symbol_rate(&self) -> f32 {
+        self.sample_rate as f32 / self.samples_per_symbol() as f32
+    }
+
+    /// Phase of the unshifted up-chirp at sample `n` of a symbol, in radians.
+    fn chirp_phase(&self, n: usize) -> f32 {
+        // f64, since the phase reaches thousands of radians over a long symbol
+        let t = n as f64 / self.sample_rate as f64;
+        let sweep_rate = self.bandwidth() as f64 / (self.samples_per_symbol() as f64 / self.sample_rate as f64);
+        let phase = 2.0 * PI * (self.low_freq as f64 * t + sweep_rate * t * t / 2.0);
+        phase.rem_euclid(2.0 * PI) as f32
+    }
+
+    /// Measures the energy of every chirp shift in one symbol, unshifted first.
+    ///
+    /// After dechirping, a shift of `k` chips is a tone on FFT bin `k` until the chirp
+    /// wraps around and on bin `k - chips` afterwards. Both bins are added together, which
+    /// recombines the two halves of the symbol coherently.
+    pub fn shift_energies(&self, samples: &[f32]) -> Vec<f32> {
+        let len = self.samples_per_symbol();
+        let mut spectrum: Vec<Complex<f32>> = self
+            .dechirp
+            .iter()
+            .zip(samples.iter().chain(std::iter::repeat(&0.0)))
+            .map(|(&reference, &s)| reference * s)
+            .collect();
+        self.fft.process(&mut spectrum);
+
+        (0..self.chips())
+            .map(|k| (spectrum[k] + spectrum[k + len - self.chips()]).norm_sqr() / len as f32)
+            .collect()
+    }
+}
+
+impl ModemTrait for CSS {
+    /// Encodes `SF` bits per symbol (MSB first), padding the last symbol with 0s.
+    ///
+    /// A shifted symbol is the unshifted chirp rotated in time. The chirp ends on a
+    /// whole number of cycles, so the phase stays continuous across the wrap.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let bits = self.bits_per_symbol();
+        let len = self.samples_per_symbol();
+        let mut signal = Vec::with_capacity(data.len().div_ceil(bits) * len);
+
+        for symbol in data.chunks(bits) {
+            let value = (0..bits).fold(0, |acc, i| (acc << 1) | symbol.get(i).copied().unwrap_or(false) as usize);
+            let offset = gray_encode(value) * self.samples_per_chip;
+            signal.extend((0..len).map(|n| self.chirp_phase((n + offset) % len).sin()));
+        }
+        Ok(signal)
+    }
+
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol()) {
+            for (mark_energy, space_energy) in self.analyze_symbol(chunk)? {
+                decoded_data.push(mark_energy > space_energy);
+            }
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol()) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// CSS symbols carry several bits, so a single-bit analysis is meaningless.
+    fn analyze_bit(&self, _samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        Err("CSS carries several bits per symbol, use `analyze_symbol` instead".into())
+    }
+
+    fn bits_per_symbol(&self) -> usize {
+        self.spreading_factor as usize
+    }
+
+    /// Analyzes one symbol, returning `(mark_energy, space_energy)` for each of its bits.
+    ///
+    /// Works like [`MFSK`](super::MFSK): the strongest shift sets every bit and the
+    /// runner-up shifts act as the noise reference.
+    fn analyze_symbol(&self, samples: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let offset = self.reference.get().unwrap_or(0);
+        let energies = self.shift_energies(samples);
+        let mut by_value = vec![0.0; self.chips()];
+        for shift in 0..self.chips() {
+            by_value[gray_decode(shift)] = energies[(shift + offset) % self.chips()];
+        }
+        Ok(symbol_bit_energies(&by_value, self.bits_per_symbol()))
+    }
+
+    fn prime(&self, preceding: &[f32]) {
+        self.reference.set((!preceding.is_empty()).then(|| {
+            let energies = self.shift_energies(preceding);
+            let measured = (0..self.chips()).max_by(|&a, &b| energies[a].total_cmp(&energies[b])).unwrap_or(0);
+            let all_mark = gray_encode(self.chips() - 1);
+            (measured + self.chips() - all_mark) % self.chips()
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn css_roundtrip_below_0_db_snr() {
+        let modem = CSS::default();
+        let bits: Vec<bool> = (0..modem.bits_per_symbol() * 12).map(|i| (i * 17 + 3) % 7 < 3).collect();
+        let signal = modem.modulate(&bits).unwrap();
+
+        // Uniform noise with 10x the chirp's power (-10 dB SNR)
+        let mut rng = StdRng::seed_from_u64(7);
+        let amplitude = 15f32.sqrt();
+        let noisy: Vec<f32> = signal.iter().map(|&s| s + rng.random_range(-amplitude..amplitude)).collect();
+        assert_eq!(modem.demodulate(&noisy).unwrap(), bits);
+    }
+}
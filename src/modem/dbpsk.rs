@@ -0,0 +1,159 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::f32::consts::PI;
+
+use super::{ModemTrait, SAMPLE_RATE, bit_llrs};
+
+/// DBPSK (Differential Binary Phase-Shift Keying) modem implementation.
+///
+/// In DBPSK the data lives in the *change* of phase between consecutive symbols:
+/// - Bit 0 keeps the carrier phase of the previous symbol.
+/// - Bit 1 flips the carrier phase by π.
+///
+/// The receiver only compares each symbol with the one before it, so an unknown
+/// acoustic delay or an inverted speaker/microphone path no longer inverts the
+/// decoded bits, unlike plain [`BPSK`](super::BPSK).
+#[derive(Debug, PartialEq)]
+pub struct DBPSK {
+    sample_rate: u32,     // Sampling rate in Hz
+    carrier_freq: f32,    // Carrier frequency in Hz
+    samples_per_bit: u32, // Number of samples used to represent one bit
+    reference: Cell<Option<(f32, f32)>>, // I/Q of the last analyzed symbol
+}
+
+impl Default for DBPSK {
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE, 1_200.0, SAMPLE_RATE / 1_200)
+    }
+}
+
+impl DBPSK {
+    /// Creates a new DBPSK modem with the given parameters.
+    pub fn new(sample_rate: u32, carrier_freq: f32, samples_per_bit: u32) -> Self {
+        Self {
+            sample_rate,
+            carrier_freq,
+            samples_per_bit,
+            reference: Cell::new(None),
+        }
+    }
+
+    /// Generates one symbol of carrier with the given absolute phase.
+    fn gen_wave(&self, phase: f32) -> impl Iterator<Item = f32> + '_ {
+        let omega = 2.0 * PI * self.carrier_freq / self.sample_rate as f32;
+        (0..self.samples_per_bit).map(move |i| (omega * i as f32 + phase).sin())
+    }
+
+    /// Projects a chunk onto the carrier, returning its `(i, q)` components.
+    ///
+    /// The reference starts at phase 0 at the beginning of the chunk, just like every
+    /// transmitted symbol does, so only the phase *difference* between chunks matters.
+    fn iq(&self, chunk: &[f32]) -> (f32, f32) {
+        let omega = 2.0 * PI * self.carrier_freq / self.sample_rate as f32;
+        chunk.iter().enumerate().fold((0.0, 0.0), |(i_acc, q_acc), (n, &sample)| {
+            let (sin, cos) = (omega * n as f32).sin_cos();
+            (i_acc + sample * sin, q_acc + sample * cos)
+        })
+    }
+
+    /// Compares a symbol with its predecessor, returning `(mark_energy, space_energy)`.
+    ///
+    /// The current symbol is projected onto the direction of the previous one. A negative
+    /// projection means the phase flipped (bit 1), a positive one that it held (bit 0).
+    /// The orthogonal component carries no information and is split between both as noise.
+    fn differential_energies(current: (f32, f32), previous: (f32, f32)) -> (f32, f32) {
+        let norm = (previous.0 * previous.0 + previous.1 * previous.1).sqrt() + f32::EPSILON;
+        let along = (current.0 * previous.0 + current.1 * previous.1) / norm;
+        let across = (current.1 * previous.0 - current.0 * previous.1) / norm;
+
+        let noise = across * across / 2.0;
+        (along.min(0.0).powi(2) + noise, along.max(0.0).powi(2) + noise)
+    }
+
+    /// Compares every symbol of a stream with the one before, returning `(mark_energy, space_energy)` per bit.
+    ///
+    /// The first symbol is compared against the phase-0 carrier the transmitter starts from.
+    fn stream_energies(&self, samples: &[f32]) -> Vec<(f32, f32)> {
+        let mut previous = (1.0, 0.0);
+        samples
+            .chunks(self.samples_per_bit as usize)
+            .map(|chunk| {
+                let current = self.iq(chunk);
+                let energies = Self::differential_energies(current, previous);
+                previous = current;
+                energies
+            })
+            .collect()
+    }
+}
+
+impl ModemTrait for DBPSK {
+    /// Encodes bits as phase transitions, starting from a phase-0 carrier.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut signal = Vec::with_capacity(data.len() * self.samples_per_bit as usize);
+        let mut inverted = false;
+        for &bit in data {
+            inverted ^= bit;
+            signal.extend(self.gen_wave(if inverted { PI } else { 0.0 }));
+        }
+        Ok(signal)
+    }
+
+    /// Decodes a DBPSK signal non-coherently, symbol by symbol.
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        Ok(self.stream_energies(samples).into_iter().map(|(mark, space)| mark > space).collect())
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        Ok(bit_llrs(&self.stream_energies(samples)))
+    }
+
+    /// Analyzes one bit's worth of samples against the previously analyzed symbol.
+    ///
+    /// Calls must be made in stream order; use [`ModemTrait::prime`] to provide the
+    /// symbol preceding the first chunk of a frame.
+    fn analyze_bit(&self, samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        let current = self.iq(samples);
+        let previous = self.reference.replace(Some(current)).unwrap_or((1.0, 0.0));
+        Ok(Self::differential_energies(current, previous))
+    }
+
+    fn prime(&self, preceding: &[f32]) {
+        self.reference
+            .set((!preceding.is_empty()).then(|| self.iq(preceding)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dbpsk_survives_inverted_path() {
+        let bits: Vec<bool> = (0..48).map(|i| (i * 5 + 1) % 3 == 0).collect();
+        let modem = DBPSK::default();
+
+        let mut signal = modem.modulate(&[false]).unwrap();
+        signal.extend(modem.modulate(&bits).unwrap());
+        let inverted: Vec<f32> = signal.iter().map(|s| -s).collect();
+
+        let decoded = modem.demodulate(&inverted).unwrap();
+        assert_eq!(&decoded[1..], &bits[..]);
+    }
+
+    #[test]
+    fn dbpsk_roundtrip_through_sonar_codec() {
+        use crate::stack::datalink::{CodecTrait, SonarCodec, SonarCodecConfig, SyncWord};
+
+        let config = SonarCodecConfig { sync_word: Some(SyncWord::BARKER_13), ..SonarCodecConfig::default() };
+        let tx = SonarCodec::new(Box::new(DBPSK::new(48_000, 1_200.0, 160)), config);
+        let mut rx = SonarCodec::new(Box::new(DBPSK::new(48_000, 1_200.0, 160)), config);
+
+        let payload: Vec<u8> = (0..32u32).map(|i| (i * 29 + 11) as u8).collect();
+        let mut signal = vec![0.0; 2_000];
+        signal.extend(tx.encode(&payload).unwrap());
+        signal.extend(vec![0.0; 2_000]);
+        let decoded: Vec<u8> = signal.chunks(4_096).filter_map(|c| rx.decode(c).unwrap()).flatten().collect();
+        assert_eq!(decoded, payload);
+    }
+}
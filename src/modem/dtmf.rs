@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::f32::consts::PI;
+
+use super::{ModemTrait, SAMPLE_RATE, bit_llrs, goertzel, gray_decode, gray_encode, symbol_bit_energies};
+
+/// Low (row) tones of the DTMF keypad in Hz.
+pub const ROW_FREQS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+/// High (column) tones of the DTMF keypad in Hz.
+pub const COL_FREQS: [f32; 4] = [1_209.0, 1_336.0, 1_477.0, 1_633.0];
+/// Keypad layout, indexed by `[row][column]`.
+pub const KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// DTMF (Dual-Tone Multi-Frequency) modem implementation.
+///
+/// Every symbol is one of the 16 telephone keypad tone pairs, so it carries 4 bits: the
+/// first two pick the row tone and the last two the column tone, each Gray coded so that
+/// confusing a tone with its neighbour costs a single bit.
+///
+/// DTMF is slow, but phone lines, VoIP codecs and gateways are all built to carry it
+/// intact, which makes it the fallback channel when other tones get mangled. Every tone
+/// burst is followed by a short silence, so gateways that regenerate DTMF still see
+/// repeated keys as separate presses.
+#[derive(Debug, PartialEq)]
+pub struct DTMF {
+    sample_rate: u32,        // Sampling rate in Hz
+    samples_per_symbol: u32, // Number of samples per symbol, gap included
+    gap: u32,                // Silent samples closing every symbol
+}
+
+impl Default for DTMF {
+    /// 10 symbols (40 bits) per second: 60 ms tone bursts and 40 ms gaps, inside the
+    /// minimum durations phone-line DTMF receivers expect.
+    fn default() -> Self {
+        const BAUD_RATE: u32 = 10;
+        Self::new(SAMPLE_RATE, SAMPLE_RATE / BAUD_RATE).with_gap(SAMPLE_RATE / 25)
+    }
+}
+
+impl DTMF {
+    /// Creates a new DTMF modem with no gap between tone bursts.
+    pub fn new(sample_rate: u32, samples_per_symbol: u32) -> Self {
+        Self { sample_rate, samples_per_symbol, gap: 0 }
+    }
+
+    /// Sets the silence closing every symbol, in samples (at most half the symbol).
+    pub fn with_gap(mut self, gap: u32) -> Self {
+        self.gap = gap.min(self.samples_per_symbol / 2);
+        self
+    }
+
+    /// Keypad key sent for a 4-bit symbol value.
+    pub fn key(value: usize) -> char {
+        let (row, col) = Self::tones(value);
+        KEYS[row][col]
+    }
+
+    /// Row and column tone indices for a 4-bit symbol value.
+    fn tones(value: usize) -> (usize, usize) {
+        (gray_encode((value >> 2) & 0b11), gray_encode(value & 0b11))
+    }
+}
+
+impl ModemTrait for DTMF {
+    /// Encodes 4 bits per symbol (MSB first), padding the last symbol with 0s.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut signal = Vec::with_capacity(data.len().div_ceil(4) * self.samples_per_symbol as usize);
+        for symbol in data.chunks(4) {
+            let value = (0..4).fold(0, |acc, i| (acc << 1) | symbol.get(i).copied().unwrap_or(false) as usize);
+            let (row, col) = Self::tones(value);
+            let omega_row = 2.0 * PI * ROW_FREQS[row] / self.sample_rate as f32;
+            let omega_col = 2.0 * PI * COL_FREQS[col] / self.sample_rate as f32;
+
+            let tone_len = self.samples_per_symbol - self.gap;
+            signal.extend((0..tone_len).map(|n| 0.5 * ((omega_row * n as f32).sin() + (omega_col * n as f32).sin())));
+            signal.extend(std::iter::repeat_n(0.0, self.gap as usize));
+        }
+        Ok(signal)
+    }
+
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol as usize) {
+            for (mark_energy, space_energy) in self.analyze_symbol(chunk)? {
+                decoded_data.push(mark_energy > space_energy);
+            }
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol as usize) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// DTMF symbols carry 4 bits, so a single-bit analysis is meaningless.
+    fn analyze_bit(&self, _samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        Err("DTMF carries 4 bits per symbol, use `analyze_symbol` instead".into())
+    }
+
+    fn bits_per_symbol(&self) -> usize {
+        4
+    }
+
+    /// Analyzes one symbol, returning `(mark_energy, space_energy)` for each of its bits.
+    ///
+    /// The row and column groups are measured separately with [`goertzel`]: the loudest
+    /// row tone sets the first two bits and the loudest column tone the last two.
+    fn analyze_symbol(&self, samples: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let tone_energies: Vec<f32> = ROW_FREQS
+            .iter()
+            .chain(&COL_FREQS)
+            .map(|&freq| goertzel(samples, freq, self.sample_rate))
+            .collect();
+        self.analyze_tones(&tone_energies)
+    }
+
+    /// The row tones, then the column tones.
+    fn tone_frequencies(&self) -> Option<Vec<f32>> {
+        Some(ROW_FREQS.iter().chain(&COL_FREQS).copied().collect())
+    }
+
+    fn analyze_tones(&self, energies: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        if energies.len() != 8 {
+            return Err(format!("DTMF listens for 8 tones, got {} energies", energies.len()).into());
+        }
+        let group_energies = |group: &[f32]| {
+            let mut by_value = vec![0.0; 4];
+            for (tone, &energy) in group.iter().enumerate() {
+                by_value[gray_decode(tone)] = energy;
+            }
+            symbol_bit_energies(&by_value, 2)
+        };
+        let mut bit_energies = group_energies(&energies[..4]);
+        bit_energies.extend(group_energies(&energies[4..]));
+        Ok(bit_energies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dtmf_roundtrip_and_keypad() {
+        let modem = DTMF::default();
+        let bits: Vec<bool> = (0..64).map(|i| (i * 9 + 4) % 5 < 2).collect();
+        let signal = modem.modulate(&bits).unwrap();
+        assert_eq!(modem.demodulate(&signal).unwrap(), bits);
+
+        let keys: String = (0..16).map(DTMF::key).collect();
+        assert_eq!(keys, "12A345B6*0D#78C9");
+    }
+}
@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+/// Tap count and step size of an [`Equalizer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqualizerConfig {
+    /// Length of the filter in samples. Echoes arriving later than half of it cannot be undone.
+    pub taps: usize,
+    /// NLMS step size, between 0 and 1: larger adapts faster, smaller settles closer.
+    pub step_size: f32,
+}
+
+impl Default for EqualizerConfig {
+    /// 513 taps, undoing echoes up to 5 ms late at 48 kHz: the early reflections of a room.
+    fn default() -> Self {
+        Self { taps: 513, step_size: 0.05 }
+    }
+}
+
+/// Adaptive LMS equalizer, undoing the echoes a room adds to the signal.
+///
+/// Hard walls reflect the sound back a few milliseconds late, smearing every symbol into
+/// the next one. The equalizer is a FIR filter sitting between the capture and the modem,
+/// trained to turn what was received back into what was sent: first on a known preamble,
+/// then on the transmitter's own signal rebuilt from every decided frame (decision-directed).
+///
+/// Taps adapt with normalized LMS, whose steps are scaled by the input power so the same
+/// step size works at any volume. The filter is centered: the middle tap passes the signal
+/// through unchanged, which is where a new equalizer starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equalizer {
+    config: EqualizerConfig,
+    taps: Vec<f32>,
+}
+
+impl Equalizer {
+    /// # Panics
+    /// If there are no taps, or the step size is not between 0 and 1.
+    pub fn new(config: EqualizerConfig) -> Self {
+        assert!(config.taps > 0, "an equalizer needs at least one tap");
+        assert!(
+            config.step_size > 0.0 && config.step_size <= 1.0,
+            "equalizer step size must be between 0 and 1, got {}",
+            config.step_size
+        );
+        let mut equalizer = Self { config, taps: Vec::new() };
+        equalizer.reset();
+        equalizer
+    }
+
+    /// Forgets the training, back to passing the signal through.
+    pub fn reset(&mut self) {
+        self.taps = vec![0.0; self.config.taps];
+        self.taps[self.config.taps / 2] = 1.0;
+    }
+
+    pub fn config(&self) -> EqualizerConfig {
+        self.config
+    }
+
+    /// Filter taps; the middle one lines up with the output sample.
+    pub fn taps(&self) -> &[f32] {
+        &self.taps
+    }
+
+    /// Input samples around `n`, newest first, zero past the ends of `input`.
+    fn window(&self, input: &[f32], n: usize) -> impl Iterator<Item = f32> {
+        let newest = n + self.taps.len() / 2;
+        (0..self.taps.len()).map(move |k| newest.checked_sub(k).and_then(|i| input.get(i)).copied().unwrap_or(0.0))
+    }
+
+    /// Filters `input[range]`, using the samples around it as context. The output has no delay.
+    pub fn equalize(&self, input: &[f32], range: Range<usize>) -> Vec<f32> {
+        range.map(|n| self.window(input, n).zip(&self.taps).map(|(x, w)| x * w).sum()).collect()
+    }
+
+    /// Adapts the taps so that `input` reads as `reference`, returning the mean squared error.
+    ///
+    /// `reference[i]` is the sample that should have been received at `input[offset + i]`;
+    /// the input around it is used for context. Training stops at the end of `input`.
+    pub fn train(&mut self, input: &[f32], reference: &[f32], offset: usize) -> f32 {
+        let mut squared_error = 0.0;
+        let mut count = 0;
+        let mut window = vec![0.0; self.taps.len()];
+        for (n, &wanted) in (offset..input.len()).zip(reference) {
+            window.iter_mut().zip(self.window(input, n)).for_each(|(slot, x)| *slot = x);
+            let output: f32 = window.iter().zip(&self.taps).map(|(x, w)| x * w).sum();
+            let power: f32 = window.iter().map(|x| x * x).sum();
+
+            let error = wanted - output;
+            let step = self.config.step_size * error / (power + f32::EPSILON);
+            self.taps.iter_mut().zip(&window).for_each(|(w, x)| *w += step * x);
+            squared_error += error * error;
+            count += 1;
+        }
+        squared_error / count.max(1) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::{BPSK, ModemTrait};
+
+    #[test]
+    fn equalizer_undoes_room_echo() {
+        let modem = BPSK::default();
+        let bits: Vec<bool> = (0..480).map(|i| (i * 11 + 5) % 7 < 3).collect();
+        let sent = modem.modulate(&bits).unwrap();
+
+        // Direct path plus two reflections, most of a symbol and two symbols late
+        let received: Vec<f32> = (0..sent.len())
+            .map(|n| {
+                let echo = |delay: usize| n.checked_sub(delay).map_or(0.0, |m| sent[m]);
+                0.5 * sent[n] + 0.4 * echo(30) - 0.3 * echo(75)
+            })
+            .collect();
+        assert_ne!(modem.demodulate(&received).unwrap(), bits);
+
+        // Train on the first three quarters, then decode the rest
+        let split = sent.len() * 3 / 4;
+        let mut equalizer = Equalizer::new(EqualizerConfig { taps: 257, step_size: 0.05 });
+        equalizer.train(&received, &sent[..split], 0);
+        let equalized = equalizer.equalize(&received, split..received.len());
+        assert_eq!(modem.demodulate(&equalized).unwrap(), bits[bits.len() * 3 / 4..]);
+    }
+}
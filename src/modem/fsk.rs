@@ -1,7 +1,6 @@
 use std::error::Error;
-use std::f32::consts::PI;
 
-use super::{ModemTrait, SAMPLE_RATE};
+use super::{ModemTrait, SAMPLE_RATE, StreamModulator, ToneModulator, bit_llrs, goertzel};
 
 // FSK (Frequency-Shift Keying) modem implementation
 #[derive(Debug, PartialEq)]
@@ -32,52 +31,47 @@ impl FSK {
         }
     }
 
-    // Helper method to generate a sine wave for a given frequency and number of samples
-    fn gen_wave(&self, frequency: f32, num_samples: u32) -> Vec<f32> {
-        let sample_period = 1.0 / self.sample_rate as f32;
-        (0..num_samples)
-            .map(|i| (2.0 * PI * frequency * (i as f32 * sample_period)).sin())
-            .collect()
+    /// Bell 202 preset: mark 1200 Hz, space 2200 Hz at 1200 baud.
+    ///
+    /// This is the AFSK flavour spoken by APRS and 1200 baud packet radio. Pair it with
+    /// NRZI and HDLC framing (see [`Ax25Codec`](crate::stack::datalink::Ax25Codec)) to
+    /// talk to TNC software. The bit length is rounded to whole samples, so sample rates
+    /// that are a multiple of 1200 Hz (such as 48 kHz) give the exact baud rate.
+    pub fn bell202(sample_rate: u32) -> Self {
+        Self::new(sample_rate, 2_200.0, 1_200.0, (sample_rate as f32 / 1_200.0).round() as u32)
     }
 
-    // Goertzel algorithm for frequency detection
-    fn correlate(&self, samples: &[f32], target_freq: f32) -> f32 {
-        let omega = 2.0 * PI * target_freq / self.sample_rate as f32;
-        let cos_omega = omega.cos();
-        let sin_omega = omega.sin();
-
-        let mut s0 = 0.0;
-        let mut s1 = 0.0;
-        let mut s2;
-
-        // Process all samples
-        for &sample in samples {
-            s2 = s1;
-            s1 = s0;
-            s0 = 2.0 * cos_omega * s1 - s2 + sample;
-        }
-        // Calculate energy (squared magnitude)
-        let real = s0 - s1 * cos_omega;
-        let imag = s1 * sin_omega;
+    /// Amateur RTTY preset: mark 2125 Hz, space 2295 Hz (170 Hz shift) at 45.45 baud.
+    ///
+    /// Use it with [`SonarCodecConfig::rtty`](crate::stack::datalink::SonarCodecConfig::rtty)
+    /// for the 5N1.5 character framing and [`Ita2`](crate::stack::datalink::Ita2) for the text.
+    pub fn rtty(sample_rate: u32) -> Self {
+        Self::new(sample_rate, 2_295.0, 2_125.0, (sample_rate as f32 / 45.45).round() as u32)
+    }
 
-        real * real + imag * imag
+    /// Continuous-phase modulator keying between the two tones.
+    fn tone_modulator(&self) -> ToneModulator {
+        ToneModulator::new(self.sample_rate, vec![(self.freq_0, 1.0), (self.freq_1, 1.0)], self.samples_per_bit)
     }
 }
 
 // Implement the ModemTrait for FSK
 impl ModemTrait for FSK {
+    /// Encodes bits as continuous-phase FSK (CPFSK).
+    ///
+    /// The oscillator phase is carried across bit boundaries, so switching tones never
+    /// jumps the waveform. That avoids the audible clicks and the spectral splatter a
+    /// phase reset at every bit would cause.
     fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
-        let mut signal = Vec::new();
-        // Generate corresponding sine waves for each bit
-        for &bit in data {
-            signal.extend(self.gen_wave(
-                if bit { self.freq_1 } else { self.freq_0 }, // freq_1 is mark, freq_0 is space
-                self.samples_per_bit,
-            ));
-        }
+        let mut signal = vec![0.0; data.len() * self.samples_per_bit as usize];
+        self.tone_modulator().fill(&mut data.iter().copied(), &mut signal);
         Ok(signal)
     }
 
+    fn streamer(&self) -> Option<Box<dyn StreamModulator + Send>> {
+        Some(Box::new(self.tone_modulator()))
+    }
+
     /// (Legacy demodulation - no longer the primary method for the new SonarCodec)
     fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
         let mut decoded_data = Vec::new();
@@ -91,6 +85,14 @@ impl ModemTrait for FSK {
         Ok(decoded_data)
     }
 
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_bit as usize) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
     // ================== NEW IMPLEMENTATION ==================
     /// Analyzes a chunk of audio, returning the energy at the mark and space frequencies.
     ///
@@ -106,11 +108,48 @@ impl ModemTrait for FSK {
     // ========================================================
     fn analyze_bit(&self, samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
         // Calculate the energy for the 'mark' frequency (bit '1')
-        let mark_energy = self.correlate(samples, self.freq_1);
+        let mark_energy = goertzel(samples, self.freq_1, self.sample_rate);
 
         // Calculate the energy for the 'space' frequency (bit '0')
-        let space_energy = self.correlate(samples, self.freq_0);
+        let space_energy = goertzel(samples, self.freq_0, self.sample_rate);
 
         Ok((mark_energy, space_energy))
     }
-}
\ No newline at end of file
+
+    /// The mark tone, then the space tone.
+    fn tone_frequencies(&self) -> Option<Vec<f32>> {
+        Some(vec![self.freq_1, self.freq_0])
+    }
+
+    fn analyze_tones(&self, energies: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let &[mark_energy, space_energy] = energies else {
+            return Err(format!("FSK listens for 2 tones, got {} energies", energies.len()).into());
+        };
+        Ok(vec![(mark_energy, space_energy)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fsk_llrs_follow_bits_and_noise() {
+        let modem = FSK::default();
+        let bits: Vec<bool> = (0..64).map(|i| (i * 5 + 2) % 7 < 3).collect();
+        let signal = modem.modulate(&bits).unwrap();
+        let noisy: Vec<f32> = signal
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| 0.5 * s + ((i * 7919 % 1000) as f32 / 1000.0 - 0.5) * 0.8)
+            .collect();
+
+        let mean_reliability = |llrs: &[f32]| llrs.iter().map(|llr| llr.abs()).sum::<f32>() / llrs.len() as f32;
+        let clean = modem.demodulate_soft(&signal).unwrap();
+        let soft = modem.demodulate_soft(&noisy).unwrap();
+        for llrs in [&clean, &soft] {
+            assert_eq!(llrs.iter().map(|&llr| llr > 0.0).collect::<Vec<_>>(), bits);
+        }
+        assert!(mean_reliability(&soft) < mean_reliability(&clean));
+    }
+}
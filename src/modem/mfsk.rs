@@ -0,0 +1,164 @@
+use std::error::Error;
+
+use super::{ModemTrait, SAMPLE_RATE, StreamModulator, ToneModulator, bit_llrs, goertzel, gray_decode, gray_encode, symbol_bit_energies};
+
+/// M-ary FSK (Multiple Frequency-Shift Keying) modem implementation.
+///
+/// Instead of the two tones of [`FSK`](super::FSK), every symbol picks one of `tones` equally
+/// spaced frequencies, carrying `log2(tones)` bits. This is the scheme behind
+/// MFSK16 and Olivia: slow, narrow and very robust in noisy rooms, because the
+/// receiver only has to find the loudest tone.
+///
+/// Symbol values are Gray coded onto the tones, so mistaking a tone for its
+/// neighbour (the most common error) costs a single bit.
+#[derive(Debug, PartialEq)]
+pub struct MFSK {
+    sample_rate: u32,        // Sampling rate in Hz
+    base_freq: f32,          // Frequency of the lowest tone in Hz
+    tone_spacing: f32,       // Distance between neighbouring tones in Hz
+    tones: usize,            // Number of tones (4, 8, 16 or 32)
+    samples_per_symbol: u32, // Number of samples per symbol
+}
+
+impl Default for MFSK {
+    fn default() -> Self {
+        const BAUD_RATE: u32 = 64;
+        const SAMPLES_PER_SYMBOL: u32 = SAMPLE_RATE / BAUD_RATE; // 750 samples per symbol
+
+        // Tone spacing equal to the symbol rate keeps the tones orthogonal
+        Self::new(SAMPLE_RATE, 1_500.0, BAUD_RATE as f32, 16, SAMPLES_PER_SYMBOL)
+    }
+}
+
+impl MFSK {
+    /// Creates a new MFSK modem.
+    ///
+    /// # Panics
+    /// If `tones` is not 4, 8, 16 or 32.
+    pub fn new(
+        sample_rate: u32,
+        base_freq: f32,
+        tone_spacing: f32,
+        tones: usize,
+        samples_per_symbol: u32,
+    ) -> Self {
+        assert!(
+            matches!(tones, 4 | 8 | 16 | 32),
+            "MFSK supports 4, 8, 16 or 32 tones, got {tones}"
+        );
+        Self {
+            sample_rate,
+            base_freq,
+            tone_spacing,
+            tones,
+            samples_per_symbol,
+        }
+    }
+
+    /// Frequency of the given tone index in Hz.
+    pub fn tone_frequency(&self, tone: usize) -> f32 {
+        self.base_freq + tone as f32 * self.tone_spacing
+    }
+
+    /// Measures the energy of every tone in a chunk of audio, lowest tone first.
+    ///
+    /// Each tone is measured with the same [`goertzel`] filter [`FSK`](super::FSK) uses for its two tones.
+    pub fn tone_energies(&self, samples: &[f32]) -> Vec<f32> {
+        (0..self.tones)
+            .map(|tone| goertzel(samples, self.tone_frequency(tone), self.sample_rate))
+            .collect()
+    }
+
+    /// Continuous-phase modulator playing the Gray-coded tone of every symbol value.
+    fn tone_modulator(&self) -> ToneModulator {
+        let tones = (0..self.tones).map(|value| (self.tone_frequency(gray_encode(value)), 1.0)).collect();
+        ToneModulator::new(self.sample_rate, tones, self.samples_per_symbol)
+    }
+}
+
+impl ModemTrait for MFSK {
+    /// Encodes `log2(tones)` bits per symbol (MSB first), padding the last symbol with 0s.
+    ///
+    /// The phase is carried across symbols so tone changes do not click.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let symbols = data.len().div_ceil(self.bits_per_symbol());
+        let mut signal = vec![0.0; symbols * self.samples_per_symbol as usize];
+        let mut modulator = self.tone_modulator();
+        let written = modulator.fill(&mut data.iter().copied(), &mut signal);
+        // The last symbol may be short of bits, padded with 0s
+        modulator.finish();
+        modulator.fill(&mut std::iter::empty(), &mut signal[written..]);
+        Ok(signal)
+    }
+
+    fn streamer(&self) -> Option<Box<dyn StreamModulator + Send>> {
+        Some(Box::new(self.tone_modulator()))
+    }
+
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol as usize) {
+            for (mark_energy, space_energy) in self.analyze_symbol(chunk)? {
+                decoded_data.push(mark_energy > space_energy);
+            }
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol as usize) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// MFSK symbols carry several bits, so a single-bit analysis is meaningless.
+    fn analyze_bit(&self, _samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        Err("MFSK carries several bits per symbol, use `analyze_symbol` instead".into())
+    }
+
+    fn bits_per_symbol(&self) -> usize {
+        self.tones.trailing_zeros() as usize
+    }
+
+    /// Analyzes one symbol, returning `(mark_energy, space_energy)` for each of its bits.
+    ///
+    /// For every bit, the mark energy is the loudest tone whose symbol value has that bit
+    /// set and the space energy the loudest tone that has it clear. The winning tone thus
+    /// sets every bit, while the runner-up tones act as the noise reference.
+    fn analyze_symbol(&self, samples: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        self.analyze_tones(&self.tone_energies(samples))
+    }
+
+    /// Every tone, lowest first.
+    fn tone_frequencies(&self) -> Option<Vec<f32>> {
+        Some((0..self.tones).map(|tone| self.tone_frequency(tone)).collect())
+    }
+
+    fn analyze_tones(&self, energies: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        if energies.len() != self.tones {
+            return Err(format!("MFSK listens for {} tones, got {} energies", self.tones, energies.len()).into());
+        }
+        let mut by_value = vec![0.0; self.tones];
+        for (tone, &energy) in energies.iter().enumerate() {
+            by_value[gray_decode(tone)] = energy;
+        }
+        Ok(symbol_bit_energies(&by_value, self.bits_per_symbol()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mfsk_roundtrip_all_tone_counts() {
+        let bits: Vec<bool> = (0..60).map(|i| (i * 11 + 2) % 7 < 3).collect();
+        for tones in [4, 8, 16, 32] {
+            let modem = MFSK::new(SAMPLE_RATE, 1_000.0, 50.0, tones, 960);
+            let signal = modem.modulate(&bits).unwrap();
+            assert_eq!(&modem.demodulate(&signal).unwrap()[..bits.len()], &bits[..]);
+        }
+    }
+}
@@ -8,12 +8,56 @@ pub use fsk::FSK;
 pub mod bpsk;
 pub use bpsk::BPSK;
 
-// pub mod qpsk;
-// pub use qpsk::QPSK;
+pub mod dbpsk;
+pub use dbpsk::DBPSK;
+
+pub mod qpsk;
+pub use qpsk::QPSK;
+
+pub mod mfsk;
+pub use mfsk::MFSK;
+
+pub mod msk;
+pub use msk::MSK;
+
+pub mod ofdm;
+pub use ofdm::{OFDM, SubcarrierModulation};
+
+pub mod css;
+pub use css::CSS;
+
+pub mod ook;
+pub use ook::OOK;
+
+pub mod dtmf;
+pub use dtmf::DTMF;
+
+pub mod pulse;
+pub use pulse::{PulseFilter, PulseShape};
+
+pub mod qam;
+pub use qam::QAM16;
+
+pub mod equalizer;
+pub use equalizer::{Equalizer, EqualizerConfig};
+
+pub mod stream;
+pub use stream::{Oscillator, StreamModulator, ToneModulator};
+
+pub mod tone_bank;
+pub use tone_bank::ToneBank;
+
+pub mod band;
+pub use band::{BandProfile, apply_fade};
 
 pub const SAMPLE_RATE: u32 = 48_000; // 48 kHz
 pub const BAUD_RATE: u32 = 1_200; // 1.2 kbps
 pub const SAMPLES_PER_BIT: u32 = SAMPLE_RATE / BAUD_RATE;
+/// Log-likelihood ratio reported for hard decisions: an error rate around 1e-9.
+pub const HARD_LLR: f32 = 20.0;
+/// Highest signal-to-noise ratio (energy) [`bit_llrs`] believes in: 30 dB, which caps the
+/// LLRs of a bit in the low thousands.
+pub const MAX_SNR: f32 = 1e3;
 
 pub fn byte_to_bits<T>(byte: u8) -> Vec<T>
 where
@@ -32,11 +76,98 @@ where
         .fold(0u8, |acc, &bit| (acc << 1) | if bit.into() { 1 } else { 0 })
 }
 
-// In src/modem/mod.rs
+/// Converts a binary value into its Gray code.
+pub fn gray_encode(value: usize) -> usize {
+    value ^ (value >> 1)
+}
+
+/// Converts a Gray code back into its binary value.
+pub fn gray_decode(mut code: usize) -> usize {
+    let mut value = code;
+    while code > 1 {
+        code >>= 1;
+        value ^= code;
+    }
+    value
+}
+
+/// Goertzel algorithm: energy (squared magnitude) of `target_freq` in a chunk of samples.
+///
+/// It evaluates a single DFT bin, which is much cheaper than an FFT when only a handful of
+/// tones matter. Every tone-based modem (FSK, MFSK, OOK, DTMF) measures its tones with it;
+/// [`ToneBank`] measures the same energies over a sliding window.
+pub fn goertzel(samples: &[f32], target_freq: f32, sample_rate: u32) -> f32 {
+    let omega = 2.0 * std::f32::consts::PI * target_freq / sample_rate as f32;
+    let cos_omega = omega.cos();
+    let sin_omega = omega.sin();
+
+    let mut s0 = 0.0;
+    let mut s1 = 0.0;
+    let mut s2;
+
+    // Process all samples
+    for &sample in samples {
+        s2 = s1;
+        s1 = s0;
+        s0 = 2.0 * cos_omega * s1 - s2 + sample;
+    }
+    // Calculate energy (squared magnitude)
+    let real = s0 - s1 * cos_omega;
+    let imag = s1 * sin_omega;
+
+    real * real + imag * imag
+}
+
+/// Turns per-symbol energies into per-bit `(mark_energy, space_energy)` pairs.
+///
+/// `energies[v]` is the energy of the symbol carrying value `v`, whose `bits` bits are
+/// sent MSB first. For every bit, the mark energy is the strongest symbol with that bit
+/// set and the space energy the strongest symbol with it clear (max-log approximation).
+pub(crate) fn symbol_bit_energies(energies: &[f32], bits: usize) -> Vec<(f32, f32)> {
+    (0..bits)
+        .map(|bit| {
+            let mask = 1 << (bits - 1 - bit);
+            energies.iter().enumerate().fold((0.0f32, 0.0f32), |(mark, space), (value, &e)| {
+                if value & mask != 0 { (mark.max(e), space) } else { (mark, space.max(e)) }
+            })
+        })
+        .collect()
+}
+
+/// Turns per-bit `(mark_energy, space_energy)` pairs into log-likelihood ratios.
+///
+/// Positive values favour a mark (`true`), negative ones a space, and the magnitude is how
+/// sure the decision is: an LLR of ±4 leaves about a 2% chance of error, ±7 about 0.1%.
+///
+/// The losing side of every bit is taken as noise and the winning side as signal plus noise.
+/// Both are averaged over the whole slice, so pass as many bits as share a channel (a frame,
+/// a transmission) for a steadier estimate. The LLR of each bit then follows a detector
+/// comparing amplitudes, `2 · A · (√mark - √space) / N`, which is what FEC decoders expect.
+/// The noise is held at least [`MAX_SNR`] below the signal: a clean synthetic signal would
+/// otherwise leave next to nothing on the losing side and send the LLRs towards infinity.
+pub fn bit_llrs(energies: &[(f32, f32)]) -> Vec<f32> {
+    let count = energies.len().max(1) as f32;
+    let noise = energies.iter().map(|&(m, s)| m.min(s)).sum::<f32>() / count;
+    let received = energies.iter().map(|&(m, s)| m.max(s)).sum::<f32>() / count;
+    let noise = noise.max(received / MAX_SNR).max(f32::MIN_POSITIVE);
+    let amplitude = (received - noise).max(0.0).sqrt();
+    energies
+        .iter()
+        .map(|&(m, s)| 2.0 * amplitude * (m.max(0.0).sqrt() - s.max(0.0).sqrt()) / noise)
+        .collect()
+}
 
 pub trait ModemTrait {
     // * Encode: bits -> signal
     fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>>;
+
+    /// Stream modulator producing the same signal as `modulate`, a buffer at a time.
+    ///
+    /// It keeps its own oscillator state, so it can feed an audio output callback for as
+    /// long as bits keep coming. Modems without one return `None`.
+    fn streamer(&self) -> Option<Box<dyn StreamModulator + Send>> {
+        None
+    }
     
     // * Decode: signal -> bits (This is now less important for the new decoder)
     fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>>;
@@ -45,6 +176,76 @@ pub trait ModemTrait {
     /// Analyzes a small chunk of audio, returning the energy at the mark and space frequencies.
     /// Returns (mark_energy, space_energy).
     fn analyze_bit(&self, samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>>;
+
+    /// Number of bits carried by each symbol (1 for binary modems like FSK or BPSK).
+    fn bits_per_symbol(&self) -> usize {
+        1
+    }
+
+    /// Analyzes one symbol's worth of audio, returning `(mark_energy, space_energy)`
+    /// for each of its bits, in the order they were passed to `modulate`.
+    ///
+    /// Binary modems get this for free from `analyze_bit`. Modems with multi-bit
+    /// symbols implement this instead and may reject `analyze_bit` outright.
+    fn analyze_symbol(&self, samples: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        Ok(vec![self.analyze_bit(samples)?])
+    }
+
+    /// Frequencies of the tones that decide every symbol, for modems that only listen for a
+    /// fixed set of tones (FSK, MFSK, OOK, DTMF).
+    ///
+    /// A receiver can then measure them with a [`ToneBank`], sliding along the signal a sample
+    /// at a time, and hand the energies to `analyze_tones` instead of calling `analyze_symbol`
+    /// at every candidate offset.
+    fn tone_frequencies(&self) -> Option<Vec<f32>> {
+        None
+    }
+
+    /// Turns the energies of `tone_frequencies` over one symbol's worth of audio into
+    /// `(mark_energy, space_energy)` per bit, as `analyze_symbol` would for that audio.
+    fn analyze_tones(&self, _energies: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        Err("this modem does not decide its symbols from a fixed set of tones".into())
+    }
+
+    /// Analyzes one symbol's worth of audio, returning the log-likelihood ratio of each of its
+    /// bits (positive for a mark), as [`bit_llrs`] computes them from `analyze_symbol`.
+    ///
+    /// The noise is estimated from this symbol alone; callers holding more symbols of the same
+    /// signal get steadier values running [`bit_llrs`] over all of their energies.
+    fn analyze_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        Ok(bit_llrs(&self.analyze_symbol(samples)?))
+    }
+
+    /// Soft counterpart of `demodulate`: one log-likelihood ratio per bit, positive for a mark.
+    ///
+    /// Modems that decode symbol by symbol estimate the noise over the whole signal. Others
+    /// only have their hard decisions to offer, reported as (very) sure LLRs of ±`HARD_LLR`.
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        Ok(self.demodulate(samples)?.into_iter().map(|bit| if bit { HARD_LLR } else { -HARD_LLR }).collect())
+    }
+
+    /// Feeds the symbol that precedes the next `analyze_bit`/`analyze_symbol` call.
+    ///
+    /// The codec always primes with an all-mark symbol, so phase modems can use it as
+    /// their reference and differential modems as the symbol to compare against.
+    /// An empty slice clears that history. Modems that analyze every symbol on its own
+    /// can ignore it.
+    fn prime(&self, _preceding: &[f32]) {}
+
+    /// Feeds back a frame the receiver decided: the `(mark_energy, space_energy)` of its bits,
+    /// as `analyze_symbol` gave them, and the bits decided from them.
+    ///
+    /// Modems adapting to the channel (OOK's levels) learn here and only here, so the many
+    /// offsets a receiver tries before deciding leave them alone. Others can ignore it.
+    fn learn(&self, _energies: &[(f32, f32)], _bits: &[bool]) {}
+
+    /// Whether a single symbol is large and self-contained enough to be the framing unit.
+    ///
+    /// When true, `SonarCodec` packs whole bytes straight into each symbol instead of
+    /// wrapping every byte in a start/stop-bit UART character.
+    fn frames_symbols(&self) -> bool {
+        false
+    }
 }
 
 // same as above but using some macro to reduce boilerplate...
@@ -81,7 +282,15 @@ macro_rules! impl_codec {
 }
 
 impl_codec!(
-    FSK, 
-    // BPSK,
-    // QPSK,
+    FSK,
+    BPSK,
+    DBPSK,
+    QPSK,
+    MFSK,
+    MSK,
+    OFDM,
+    CSS,
+    OOK,
+    DTMF,
+    QAM16,
 );
@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::f32::consts::{FRAC_PI_4, PI};
+
+use super::{ModemTrait, SAMPLE_RATE, bit_llrs};
+
+/// MSK (Minimum-Shift Keying) modem, with an optional Gaussian filter for GMSK.
+///
+/// MSK is continuous-phase FSK with the smallest tone spacing that keeps the two
+/// tones orthogonal: half the bit rate. The carrier phase moves by exactly ±90° over
+/// every bit, which gives a constant envelope and a compact spectrum.
+///
+/// With a Gaussian filter (GMSK) the frequency steps between bits are smoothed as well.
+/// The bandwidth-time product `bt` sets how much: 0.3 (GSM) or 0.5 (Bluetooth) are
+/// typical, lower values are narrower but smear neighbouring bits together.
+#[derive(Debug, PartialEq)]
+pub struct MSK {
+    sample_rate: u32,     // Sampling rate in Hz
+    center_freq: f32,     // Center frequency in Hz, halfway between mark and space
+    samples_per_bit: u32, // Number of samples per bit
+    bt: Option<f32>,      // Gaussian filter bandwidth-time product (None for plain MSK)
+}
+
+impl Default for MSK {
+    fn default() -> Self {
+        const BAUD_RATE: u32 = 300;
+        Self::new(SAMPLE_RATE, 1_800.0, SAMPLE_RATE / BAUD_RATE)
+    }
+}
+
+impl MSK {
+    /// Span of the Gaussian filter, in bits.
+    const GAUSSIAN_SPAN: u32 = 4;
+
+    /// Creates a new (plain) MSK modem.
+    pub fn new(sample_rate: u32, center_freq: f32, samples_per_bit: u32) -> Self {
+        Self {
+            sample_rate,
+            center_freq,
+            samples_per_bit,
+            bt: None,
+        }
+    }
+
+    /// Creates a new GMSK modem with the given bandwidth-time product.
+    pub fn gmsk(sample_rate: u32, center_freq: f32, samples_per_bit: u32, bt: f32) -> Self {
+        Self {
+            bt: Some(bt),
+            ..Self::new(sample_rate, center_freq, samples_per_bit)
+        }
+    }
+
+    /// Frequency deviation from the center frequency in Hz (a quarter of the bit rate).
+    fn deviation(&self) -> f32 {
+        self.sample_rate as f32 / self.samples_per_bit as f32 / 4.0
+    }
+
+    /// Gaussian pulse-shaping taps, normalized to unity DC gain.
+    fn gaussian_taps(&self, bt: f32) -> Vec<f32> {
+        let len = (Self::GAUSSIAN_SPAN * self.samples_per_bit) as usize | 1;
+        let half = (len / 2) as f32;
+        // Standard deviation of the Gaussian in samples: sqrt(ln 2) / (2π BT) bit periods
+        let sigma = 2f32.ln().sqrt() / (2.0 * PI * bt) * self.samples_per_bit as f32;
+        let taps: Vec<f32> = (0..len)
+            .map(|i| (-((i as f32 - half) / sigma).powi(2) / 2.0).exp())
+            .collect();
+        let sum: f32 = taps.iter().sum();
+        taps.into_iter().map(|t| t / sum).collect()
+    }
+
+    /// Builds the per-sample frequency pulse train (±1 per bit), Gaussian filtered for GMSK.
+    fn frequency_pulses(&self, data: &[bool]) -> Vec<f32> {
+        let nrz: Vec<f32> = data
+            .iter()
+            .flat_map(|&bit| std::iter::repeat_n(if bit { 1.0 } else { -1.0 }, self.samples_per_bit as usize))
+            .collect();
+
+        let Some(bt) = self.bt else { return nrz };
+        let taps = self.gaussian_taps(bt);
+        let half = taps.len() / 2;
+        // Centered convolution; the edges are held at the first/last bit's level
+        (0..nrz.len())
+            .map(|n| {
+                taps.iter().enumerate().fold(0.0, |acc, (k, &tap)| {
+                    let idx = (n + k).saturating_sub(half).min(nrz.len() - 1);
+                    acc + tap * nrz[idx]
+                })
+            })
+            .collect()
+    }
+
+    /// Mixes a chunk starting `offset` samples into the bit down to baseband around the
+    /// center frequency.
+    fn baseband(&self, chunk: &[f32], offset: usize) -> (f32, f32) {
+        let omega = 2.0 * PI * self.center_freq / self.sample_rate as f32;
+        chunk.iter().enumerate().fold((0.0, 0.0), |(re, im), (n, &sample)| {
+            let (sin, cos) = (omega * (offset + n) as f32).sin_cos();
+            (re + sample * cos, im - sample * sin)
+        })
+    }
+}
+
+impl ModemTrait for MSK {
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let omega = 2.0 * PI * self.center_freq / self.sample_rate as f32;
+        let deviation = 2.0 * PI * self.deviation() / self.sample_rate as f32;
+        let mut phase = 0.0f32;
+        Ok(self
+            .frequency_pulses(data)
+            .into_iter()
+            .map(|pulse| {
+                let sample = phase.sin();
+                phase = (phase + omega + deviation * pulse) % (2.0 * PI);
+                sample
+            })
+            .collect())
+    }
+
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks(self.samples_per_bit as usize) {
+            let (mark_energy, space_energy) = self.analyze_bit(chunk)?;
+            decoded_data.push(mark_energy > space_energy);
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_bit as usize) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// Analyzes one bit with a differential phase discriminator.
+    ///
+    /// The chunk is mixed down around the center frequency, against one carrier reference
+    /// running across the whole bit, and split in halves. Between the two halves the phase
+    /// advances by +45° for a mark and -45° for a space; those two directions are
+    /// orthogonal, so the projections onto them act as the mark and space energies.
+    /// Projections pointing the wrong way are counted as noise.
+    fn analyze_bit(&self, samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        let (first, second) = samples.split_at(samples.len() / 2);
+        let (re1, im1) = self.baseband(first, 0);
+        let (re2, im2) = self.baseband(second, first.len());
+
+        // Rotation from the first half to the second: z2 * conj(z1) / |z1|
+        let norm = (re1 * re1 + im1 * im1).sqrt() + f32::EPSILON;
+        let (re, im) = ((re2 * re1 + im2 * im1) / norm, (im2 * re1 - re2 * im1) / norm);
+
+        let (sin, cos) = FRAC_PI_4.sin_cos();
+        let towards_mark = re * cos + im * sin;
+        let towards_space = re * cos - im * sin;
+
+        let noise = (towards_mark.min(0.0).powi(2) + towards_space.min(0.0).powi(2)) / 2.0;
+        Ok((
+            towards_mark.max(0.0).powi(2) + noise,
+            towards_space.max(0.0).powi(2) + noise,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msk_and_gmsk_roundtrip() {
+        let bits: Vec<bool> = (0..64).map(|i| (i * 13 + 5) % 9 < 4).collect();
+        for modem in [MSK::default(), MSK::gmsk(SAMPLE_RATE, 1_800.0, 160, 0.5)] {
+            let signal = modem.modulate(&bits).unwrap();
+            assert_eq!(modem.demodulate(&signal).unwrap(), bits);
+        }
+    }
+
+    #[test]
+    fn msk_roundtrip_at_any_center_frequency() {
+        let bits: Vec<bool> = (0..64).map(|i| (i * 7 + 3) % 5 < 2).collect();
+        for center_freq in [1_000.0, 1_234.0, 1_500.0, 2_000.0, 2_400.0] {
+            for samples_per_bit in [160, 147, 81] {
+                let modem = MSK::new(SAMPLE_RATE, center_freq, samples_per_bit);
+                let signal = modem.modulate(&bits).unwrap();
+                assert_eq!(modem.demodulate(&signal).unwrap(), bits, "{center_freq} Hz, {samples_per_bit} samples per bit");
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use super::{ModemTrait, SAMPLE_RATE, bit_llrs};
+
+/// Constellation carried by every OFDM data subcarrier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubcarrierModulation {
+    /// One bit per subcarrier (±1).
+    Bpsk,
+    /// Two Gray-coded bits per subcarrier (±1 ± j), same mapping as [`QPSK`](super::QPSK).
+    Qpsk,
+}
+
+impl SubcarrierModulation {
+    fn bits(self) -> usize {
+        match self {
+            SubcarrierModulation::Bpsk => 1,
+            SubcarrierModulation::Qpsk => 2,
+        }
+    }
+}
+
+/// OFDM (Orthogonal Frequency-Division Multiplexing) modem implementation.
+///
+/// Every symbol is an inverse FFT of many subcarriers sent in parallel, each one carrying
+/// BPSK or QPSK. A few subcarriers are pilots with known values: the receiver uses them to
+/// estimate the room's frequency response (gain and phase per subcarrier) and undoes it
+/// before deciding the data carriers. Each symbol is preceded by a cyclic prefix, a copy
+/// of its tail, so echoes shorter than the prefix do not bleed into the next symbol.
+///
+/// One OFDM symbol carries `data carriers * bits per carrier` bits, which makes it a
+/// natural framing unit: [`ModemTrait::frames_symbols`] tells `SonarCodec` to pack whole
+/// bytes into each symbol instead of UART characters.
+pub struct OFDM {
+    sample_rate: u32,                      // Sampling rate in Hz
+    fft_size: usize,                       // FFT length (samples per symbol without prefix)
+    cyclic_prefix: usize,                  // Cyclic prefix length in samples
+    data_bins: Vec<usize>,                 // FFT bins carrying data
+    pilot_bins: Vec<usize>,                // FFT bins carrying known pilots
+    modulation: SubcarrierModulation,      // Constellation on each data bin
+    forward: Arc<dyn Fft<f32>>,            // FFT used by the receiver
+    inverse: Arc<dyn Fft<f32>>,            // Inverse FFT used by the transmitter
+}
+
+impl fmt::Debug for OFDM {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OFDM")
+            .field("sample_rate", &self.sample_rate)
+            .field("fft_size", &self.fft_size)
+            .field("cyclic_prefix", &self.cyclic_prefix)
+            .field("data_carriers", &self.data_bins.len())
+            .field("pilot_carriers", &self.pilot_bins.len())
+            .field("modulation", &self.modulation)
+            .finish()
+    }
+}
+
+impl Default for OFDM {
+    /// 512-point FFT (93.75 Hz spacing) over 500-8000 Hz, 128-sample prefix and a pilot on
+    /// every 4th carrier: 75 symbols per second of 60 QPSK carriers, i.e. 9000 bits/s.
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE, 512, 128, 500.0, 8_000.0, 4, SubcarrierModulation::Qpsk)
+    }
+}
+
+impl OFDM {
+    /// Creates a new OFDM modem.
+    ///
+    /// Every subcarrier whose frequency falls inside `low_freq..=high_freq` is used, and
+    /// every `pilot_spacing`-th one (starting with the lowest) becomes a pilot. The last
+    /// carrier is always a pilot too, so data carriers are interpolated, never extrapolated.
+    ///
+    /// # Panics
+    /// If the band lies above the Nyquist frequency or holds fewer than three carriers.
+    pub fn new(
+        sample_rate: u32,
+        fft_size: usize,
+        cyclic_prefix: usize,
+        low_freq: f32,
+        high_freq: f32,
+        pilot_spacing: usize,
+        modulation: SubcarrierModulation,
+    ) -> Self {
+        assert!(
+            high_freq < sample_rate as f32 / 2.0,
+            "OFDM band must stay below the Nyquist frequency ({} Hz)",
+            sample_rate / 2
+        );
+        let bin_width = sample_rate as f32 / fft_size as f32;
+        let first = (low_freq / bin_width).ceil().max(1.0) as usize;
+        let last = (high_freq / bin_width).floor() as usize;
+        assert!(last >= first + 2, "OFDM band is too narrow for {fft_size} bins");
+
+        let spacing = pilot_spacing.max(2);
+        let (pilot_bins, data_bins) = (first..=last)
+            .partition(|&bin| (bin - first).is_multiple_of(spacing) || bin == last);
+
+        let mut planner = FftPlanner::new();
+        Self {
+            sample_rate,
+            fft_size,
+            cyclic_prefix,
+            data_bins,
+            pilot_bins,
+            modulation,
+            forward: planner.plan_fft_forward(fft_size),
+            inverse: planner.plan_fft_inverse(fft_size),
+        }
+    }
+
+    /// Number of samples in one symbol, cyclic prefix included.
+    pub fn samples_per_symbol(&self) -> usize {
+        self.fft_size + self.cyclic_prefix
+    }
+
+    /// Symbol rate in symbols per second.
+    pub fn symbol_rate(&self) -> f32 {
+        self.sample_rate as f32 / self.samples_per_symbol() as f32
+    }
+
+    /// Known value of the pilot on `bin`: a ±1 pattern that avoids a periodic pulse train.
+    fn pilot_value(bin: usize) -> Complex<f32> {
+        Complex::new(if bin.count_ones().is_multiple_of(2) { 1.0 } else { -1.0 }, 0.0)
+    }
+
+    /// Maps the bits of one subcarrier onto its constellation point.
+    fn map(&self, bits: &[bool]) -> Complex<f32> {
+        let level = |bit: bool| if bit { -1.0 } else { 1.0 };
+        match self.modulation {
+            SubcarrierModulation::Bpsk => Complex::new(level(bits[0]), 0.0),
+            SubcarrierModulation::Qpsk => {
+                Complex::new(level(bits[0]), level(bits[1])) * std::f32::consts::FRAC_1_SQRT_2
+            }
+        }
+    }
+
+    /// Generates one OFDM symbol (cyclic prefix included) for exactly `bits_per_symbol` bits.
+    fn gen_symbol(&self, bits: &[bool]) -> Vec<f32> {
+        let mut spectrum = vec![Complex::new(0.0, 0.0); self.fft_size];
+        for &bin in &self.pilot_bins {
+            spectrum[bin] = Self::pilot_value(bin);
+        }
+        for (&bin, carrier_bits) in self.data_bins.iter().zip(bits.chunks(self.modulation.bits())) {
+            spectrum[bin] = self.map(carrier_bits);
+        }
+        // Hermitian symmetry makes the time-domain signal real
+        for bin in 1..self.fft_size / 2 {
+            spectrum[self.fft_size - bin] = spectrum[bin].conj();
+        }
+        self.inverse.process(&mut spectrum);
+
+        // Keep the RMS level around 0.25 so the peaks stay inside [-1, 1]
+        let carriers = (self.data_bins.len() + self.pilot_bins.len()) as f32;
+        let scale = 0.25 / (2.0 * carriers).sqrt();
+        let body: Vec<f32> = spectrum.iter().map(|c| c.re * scale).collect();
+
+        let mut symbol = Vec::with_capacity(self.samples_per_symbol());
+        symbol.extend_from_slice(&body[self.fft_size - self.cyclic_prefix..]);
+        symbol.extend_from_slice(&body);
+        symbol
+    }
+
+    /// Estimates the channel on every data bin from the pilots.
+    ///
+    /// The common phase slope (timing offset inside the prefix) is removed first, so the
+    /// linear interpolation between pilots only has to follow the room's own response.
+    fn estimate_channel(&self, spectrum: &[Complex<f32>]) -> Vec<Complex<f32>> {
+        let pilots: Vec<(usize, Complex<f32>)> = self
+            .pilot_bins
+            .iter()
+            .map(|&bin| (bin, spectrum[bin] / Self::pilot_value(bin)))
+            .collect();
+
+        let gap = self.pilot_bins[1] - self.pilot_bins[0];
+        let slope = pilots
+            .windows(2)
+            .filter(|pair| pair[1].0 - pair[0].0 == gap)
+            .map(|pair| pair[1].1 * pair[0].1.conj())
+            .sum::<Complex<f32>>()
+            .arg()
+            / gap as f32;
+        let flatten = |bin: usize| Complex::from_polar(1.0, -slope * bin as f32);
+        let flat: Vec<(usize, Complex<f32>)> =
+            pilots.iter().map(|&(bin, h)| (bin, h * flatten(bin))).collect();
+
+        self.data_bins
+            .iter()
+            .map(|&bin| {
+                let right = flat.iter().position(|&(p, _)| p > bin).unwrap_or(flat.len() - 1);
+                let (lo_bin, lo) = flat[right - 1];
+                let (hi_bin, hi) = flat[right];
+                let t = (bin - lo_bin) as f32 / (hi_bin - lo_bin) as f32;
+                (lo * (1.0 - t) + hi * t) / flatten(bin)
+            })
+            .collect()
+    }
+}
+
+impl ModemTrait for OFDM {
+    /// Encodes the bits symbol by symbol, padding the last symbol with 0s.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let bits = self.bits_per_symbol();
+        let mut signal = Vec::with_capacity(data.len().div_ceil(bits) * self.samples_per_symbol());
+        for chunk in data.chunks(bits) {
+            let mut symbol_bits = chunk.to_vec();
+            symbol_bits.resize(bits, false);
+            signal.extend(self.gen_symbol(&symbol_bits));
+        }
+        Ok(signal)
+    }
+
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks_exact(self.samples_per_symbol()) {
+            for (mark_energy, space_energy) in self.analyze_symbol(chunk)? {
+                decoded_data.push(mark_energy > space_energy);
+            }
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks_exact(self.samples_per_symbol()) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// OFDM symbols carry many bits, so a single-bit analysis is meaningless.
+    fn analyze_bit(&self, _samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        Err("OFDM carries many bits per symbol, use `analyze_symbol` instead".into())
+    }
+
+    fn bits_per_symbol(&self) -> usize {
+        self.data_bins.len() * self.modulation.bits()
+    }
+
+    /// Analyzes one OFDM symbol (cyclic prefix included), returning per-bit energies.
+    ///
+    /// The prefix is skipped, the body goes through the FFT and every data carrier is
+    /// phase-corrected with the pilot-based channel estimate. Each axis then decides a
+    /// bit by its sign, and whatever does not fit the constellation counts as noise.
+    fn analyze_symbol(&self, samples: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let mut spectrum: Vec<Complex<f32>> = samples
+            .iter()
+            .skip(self.cyclic_prefix)
+            .map(|&s| Complex::new(s, 0.0))
+            .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+            .take(self.fft_size)
+            .collect();
+        self.forward.process(&mut spectrum);
+
+        let channel = self.estimate_channel(&spectrum);
+        let split = |axis: f32, noise: f32| (axis.min(0.0).powi(2) + noise, axis.max(0.0).powi(2) + noise);
+
+        let mut energies = Vec::with_capacity(self.bits_per_symbol());
+        for (&bin, h) in self.data_bins.iter().zip(channel) {
+            // Phase-correct without dividing out the gain, so weak carriers weigh less
+            let z = spectrum[bin] * h.conj() / (h.norm() + f32::EPSILON);
+            match self.modulation {
+                SubcarrierModulation::Bpsk => energies.push(split(z.re, z.im * z.im / 2.0)),
+                SubcarrierModulation::Qpsk => {
+                    let noise = ((z.re.abs() - z.im.abs()) / 2.0).powi(2);
+                    energies.push(split(z.re, noise));
+                    energies.push(split(z.im, noise));
+                }
+            }
+        }
+        Ok(energies)
+    }
+
+    fn frames_symbols(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ofdm_roundtrip_through_echo() {
+        let modem = OFDM::default();
+        let bits: Vec<bool> = (0..modem.bits_per_symbol() * 3).map(|i| (i * 31 + 7) % 11 < 5).collect();
+        let signal = modem.modulate(&bits).unwrap();
+
+        // Direct path plus a weaker echo 40 samples later, well inside the cyclic prefix
+        let echoed: Vec<f32> = (0..signal.len())
+            .map(|n| signal[n] + if n >= 40 { 0.5 * signal[n - 40] } else { 0.0 })
+            .collect();
+        assert_eq!(modem.demodulate(&echoed).unwrap(), bits);
+    }
+}
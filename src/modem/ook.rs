@@ -0,0 +1,199 @@
+use std::cell::Cell;
+use std::error::Error;
+
+use super::{ModemTrait, SAMPLE_RATE, StreamModulator, ToneModulator, bit_llrs, goertzel};
+
+/// OOK (On-Off Keying) modem implementation, the simplest form of ASK.
+///
+/// A mark is the tone switched on, a space is silence. That is all a piezo buzzer
+/// driven from a GPIO pin can do, so this is the modem for the cheapest transmitters.
+///
+/// With no second tone to compare against, `analyze_bit` measures the tone energy against
+/// an adaptive threshold instead. The modem learns the noise floor from the quiet bits and
+/// the "on" level from the loud ones of the frames the receiver decides (see
+/// [`ModemTrait::learn`]), and puts the threshold between the two. The all-mark symbol
+/// passed to [`ModemTrait::prime`] stands in for the on level, so the very first frames can
+/// be told apart before anything is learned. `SonarCodec` then sees `(tone, threshold)` as
+/// its mark/space energies, which keeps its confidence meaningful.
+#[derive(Debug, PartialEq)]
+pub struct OOK {
+    sample_rate: u32,                // Sampling rate in Hz
+    freq: f32,                       // Tone frequency in Hz
+    samples_per_bit: u32,            // Number of samples per bit
+    noise_floor: Cell<Option<f32>>,  // Tone energy while off, as learned so far
+    on_level: Cell<Option<f32>>,     // Tone energy while on, as learned so far
+    reference: Cell<Option<f32>>,    // Tone energy of the last primed all-mark symbol
+}
+
+impl Default for OOK {
+    /// 2.7 kHz (a common piezo resonance) at 200 baud.
+    fn default() -> Self {
+        const BAUD_RATE: u32 = 200;
+        Self::new(SAMPLE_RATE, 2_700.0, SAMPLE_RATE / BAUD_RATE)
+    }
+}
+
+impl OOK {
+    /// Minimum ratio between the threshold and the noise floor (6 dB).
+    const FLOOR_MARGIN: f32 = 4.0;
+    /// Lowest noise floor relative to the on level (-40 dB). Over digital silence the floor
+    /// would reach 0, and a window starting a sample off the bit grid then reads as on.
+    const MIN_FLOOR: f32 = 1e-4;
+    /// How fast the levels follow new measurements (per analyzed bit).
+    const TRACKING: f32 = 0.05;
+
+    pub fn new(sample_rate: u32, freq: f32, samples_per_bit: u32) -> Self {
+        Self {
+            sample_rate,
+            freq,
+            samples_per_bit,
+            noise_floor: Cell::new(None),
+            on_level: Cell::new(None),
+            reference: Cell::new(None),
+        }
+    }
+
+    /// Current decision threshold: the geometric mean of the noise floor and the on level
+    /// (that of the primed symbol, until one is learned), but never closer than `FLOOR_MARGIN`
+    /// to the floor.
+    pub fn threshold(&self) -> f32 {
+        let floor = self.noise_floor.get().unwrap_or(0.0).max(f32::MIN_POSITIVE);
+        match self.on_level.get().or(self.reference.get()) {
+            Some(on) => {
+                let floor = floor.max(on * Self::MIN_FLOOR);
+                (floor * Self::FLOOR_MARGIN).max((floor * on).sqrt())
+            }
+            None => floor * Self::FLOOR_MARGIN,
+        }
+    }
+
+    /// Whether any level has been learned yet.
+    fn learned(&self) -> bool {
+        self.noise_floor.get().is_some() || self.on_level.get().is_some()
+    }
+
+    /// Forgets the learned levels, e.g. after moving to a different room.
+    pub fn reset_levels(&self) {
+        self.noise_floor.set(None);
+        self.on_level.set(None);
+    }
+
+    /// The tone at full or no amplitude, on a free-running oscillator.
+    fn tone_modulator(&self) -> ToneModulator {
+        ToneModulator::new(self.sample_rate, vec![(self.freq, 0.0), (self.freq, 1.0)], self.samples_per_bit)
+    }
+
+    /// Folds the tone energy of a decided bit into the on level or the floor.
+    ///
+    /// The floor drops quickly and rises slowly, so loud bits that slip under the threshold
+    /// cannot drag it up.
+    fn track(&self, energy: f32, on: bool) {
+        let ema = |level: Option<f32>, rate: f32| Some(level.map_or(energy, |l| l + rate * (energy - l)));
+        if on {
+            self.on_level.set(ema(self.on_level.get(), Self::TRACKING));
+        } else {
+            let rate = match self.noise_floor.get() {
+                Some(floor) if energy < floor => 0.5,
+                _ => Self::TRACKING,
+            };
+            self.noise_floor.set(ema(self.noise_floor.get(), rate));
+        }
+    }
+}
+
+impl ModemTrait for OOK {
+    /// Keys the tone on for marks and off for spaces.
+    ///
+    /// The oscillator keeps running while the tone is off, so bursts stay phase coherent.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut signal = vec![0.0; data.len() * self.samples_per_bit as usize];
+        self.tone_modulator().fill(&mut data.iter().copied(), &mut signal);
+        Ok(signal)
+    }
+
+    fn streamer(&self) -> Option<Box<dyn StreamModulator + Send>> {
+        Some(Box::new(self.tone_modulator()))
+    }
+
+    /// Decides the bits one by one, learning the levels from every decision.
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks(self.samples_per_bit as usize) {
+            let (energy, threshold) = self.analyze_bit(chunk)?;
+            // Nothing learned yet: the first bit is taken as quiet, like `learn` takes it
+            let bit = self.learned() && energy > threshold;
+            self.learn(&[(energy, threshold)], &[bit]);
+            decoded_data.push(bit);
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_bit as usize) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// Analyzes one bit, returning `(tone_energy, threshold)`. The learned levels stay as
+    /// they are.
+    fn analyze_bit(&self, samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        let energy = goertzel(samples, self.freq, self.sample_rate);
+        Ok(self.analyze_tones(&[energy])?[0])
+    }
+
+    fn tone_frequencies(&self) -> Option<Vec<f32>> {
+        Some(vec![self.freq])
+    }
+
+    /// Same as `analyze_bit`, from the tone energy.
+    fn analyze_tones(&self, energies: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let &[energy] = energies else {
+            return Err(format!("OOK listens for 1 tone, got {} energies", energies.len()).into());
+        };
+        Ok(vec![(energy, self.threshold())])
+    }
+
+    /// Takes the primed all-mark symbol's tone energy as the on level of the next frame.
+    fn prime(&self, preceding: &[f32]) {
+        self.reference
+            .set((!preceding.is_empty()).then(|| goertzel(preceding, self.freq, self.sample_rate)));
+    }
+
+    /// Learns the floor from the decided spaces and the on level from the marks. With nothing
+    /// learned yet the first bit is taken as quiet, so the receiver should hear some silence
+    /// before the first transmission.
+    fn learn(&self, energies: &[(f32, f32)], bits: &[bool]) {
+        for (&(energy, _), &bit) in energies.iter().zip(bits) {
+            self.track(energy, bit && self.learned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ook_learns_its_threshold_from_noise() {
+        let modem = OOK::default();
+        let mut bits = vec![false; 8];
+        bits.extend((0..64).map(|i| (i * 5 + 1) % 7 < 4));
+        let signal = modem.modulate(&bits).unwrap();
+
+        let noisy: Vec<f32> = signal
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| 0.5 * s + ((i * 7919 % 1000) as f32 / 1000.0 - 0.5) * 0.2)
+            .collect();
+        assert_eq!(modem.demodulate(&noisy).unwrap(), bits);
+
+        // Analyzing leaves the levels alone, however often a receiver probes
+        let threshold = modem.threshold();
+        for chunk in noisy.chunks(97) {
+            modem.analyze_bit(chunk).unwrap();
+        }
+        assert_eq!(modem.threshold(), threshold);
+    }
+}
@@ -0,0 +1,218 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+use super::{ModemTrait, SAMPLE_RATE, bit_llrs};
+
+/// QPSK (Quadrature Phase-Shift Keying) modem implementation.
+///
+/// Every symbol carries two bits, one on the in-phase (sine) axis and one on the
+/// quadrature (cosine) axis, so it moves twice the data of [`BPSK`](super::BPSK)
+/// in the same bandwidth. The mapping is Gray coded: neighbouring constellation
+/// points differ in a single bit, so the most likely symbol error costs one bit.
+///
+/// | bits | I  | Q  | phase |
+/// |------|----|----|-------|
+/// | 00   | +1 | +1 |  45°  |
+/// | 01   | +1 | -1 | -45°  |
+/// | 11   | -1 | -1 | -135° |
+/// | 10   | -1 | +1 |  135° |
+///
+/// The carrier runs on across symbols, so chunks after a prime must follow each other:
+/// each is mixed down at its offset from the primed symbol. When the codec primes an
+/// all-mark (`11`) symbol, the constellation is rotated so that symbol lands back on
+/// -135°, which removes the four-fold phase ambiguity of the acoustic path.
+#[derive(Debug, PartialEq)]
+pub struct QPSK {
+    sample_rate: u32,        // Sampling rate in Hz
+    carrier_freq: f32,       // Carrier frequency in Hz
+    samples_per_symbol: u32, // Number of samples used to represent one symbol (2 bits)
+    reference: Cell<Option<(f32, f32)>>, // I/Q of the last primed all-mark symbol
+    offset: Cell<usize>,                 // Samples from the primed symbol to the next chunk
+}
+
+impl Default for QPSK {
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE, 1_200.0, SAMPLE_RATE / 1_200)
+    }
+}
+
+impl QPSK {
+    /// Creates a new QPSK modem with the given parameters.
+    pub fn new(sample_rate: u32, carrier_freq: f32, samples_per_symbol: u32) -> Self {
+        Self {
+            sample_rate,
+            carrier_freq,
+            samples_per_symbol,
+            reference: Cell::new(None),
+            offset: Cell::new(0),
+        }
+    }
+
+    /// Maps a bit to its axis amplitude (`false` -> +1, `true` -> -1).
+    fn level(bit: bool) -> f32 {
+        if bit { -FRAC_1_SQRT_2 } else { FRAC_1_SQRT_2 }
+    }
+
+    /// Generates one symbol carrying the bit pair `(b0, b1)`, starting `start` samples into
+    /// the signal so the carrier runs on across symbols.
+    fn gen_wave(&self, b0: bool, b1: bool, start: usize) -> impl Iterator<Item = f32> + '_ {
+        let omega = 2.0 * PI * self.carrier_freq / self.sample_rate as f32;
+        let (i, q) = (Self::level(b0), Self::level(b1));
+        (start..start + self.samples_per_symbol as usize).map(move |n| {
+            let (sin, cos) = (omega * n as f32).sin_cos();
+            i * sin + q * cos
+        })
+    }
+
+    /// Rotates `(i, q)` by the phase that moves `reference` onto the all-mark point (-135°).
+    fn derotate((i, q): (f32, f32), (ref_i, ref_q): (f32, f32)) -> (f32, f32) {
+        let norm = (ref_i * ref_i + ref_q * ref_q).sqrt() + f32::EPSILON;
+        // (i + jq) * conj(ref) / |ref|, then rotated by -135°
+        let (re, im) = ((i * ref_i + q * ref_q) / norm, (q * ref_i - i * ref_q) / norm);
+        (-FRAC_1_SQRT_2 * (re - im), -FRAC_1_SQRT_2 * (re + im))
+    }
+
+    /// Projects a chunk starting `offset` samples into the signal onto both carrier axes,
+    /// returning its `(i, q)` components.
+    fn iq(&self, chunk: &[f32], offset: usize) -> (f32, f32) {
+        let omega = 2.0 * PI * self.carrier_freq / self.sample_rate as f32;
+        chunk.iter().enumerate().fold((0.0, 0.0), |(i_acc, q_acc), (n, &sample)| {
+            let (sin, cos) = (omega * (offset + n) as f32).sin_cos();
+            (i_acc + sample * sin, q_acc + sample * cos)
+        })
+    }
+}
+
+impl ModemTrait for QPSK {
+    /// Encodes bits two at a time; an odd trailing bit is paired with a 0.
+    fn modulate(&self, data: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut signal = Vec::with_capacity(data.len().div_ceil(2) * self.samples_per_symbol as usize);
+        for pair in data.chunks(2) {
+            signal.extend(self.gen_wave(pair[0], pair.get(1).copied().unwrap_or(false), signal.len()));
+        }
+        Ok(signal)
+    }
+
+    fn demodulate(&self, samples: &[f32]) -> Result<Vec<bool>, Box<dyn Error>> {
+        self.offset.set(0);
+        let mut decoded_data = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol as usize) {
+            for (mark_energy, space_energy) in self.analyze_symbol(chunk)? {
+                decoded_data.push(mark_energy > space_energy);
+            }
+        }
+        Ok(decoded_data)
+    }
+
+    fn demodulate_soft(&self, samples: &[f32]) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.offset.set(0);
+        let mut energies = Vec::new();
+        for chunk in samples.chunks(self.samples_per_symbol as usize) {
+            energies.extend(self.analyze_symbol(chunk)?);
+        }
+        Ok(bit_llrs(&energies))
+    }
+
+    /// QPSK symbols carry two bits, so a single-bit analysis is meaningless.
+    fn analyze_bit(&self, _samples: &[f32]) -> Result<(f32, f32), Box<dyn Error>> {
+        Err("QPSK carries 2 bits per symbol, use `analyze_symbol` instead".into())
+    }
+
+    fn bits_per_symbol(&self) -> usize {
+        2
+    }
+
+    /// Analyzes one symbol, returning `(mark_energy, space_energy)` for both of its bits.
+    ///
+    /// Each axis decides one bit by its sign. A clean QPSK symbol has equal magnitude on
+    /// both axes, so their mismatch is counted as noise on both bits.
+    fn analyze_symbol(&self, samples: &[f32]) -> Result<Vec<(f32, f32)>, Box<dyn Error>> {
+        let iq = self.iq(samples, self.offset.replace(self.offset.get() + samples.len()));
+        let (i, q) = match self.reference.get() {
+            Some(reference) => Self::derotate(iq, reference),
+            None => iq,
+        };
+        let noise = ((i.abs() - q.abs()) / 2.0).powi(2);
+        Ok([i, q]
+            .into_iter()
+            .map(|axis| (axis.min(0.0).powi(2) + noise, axis.max(0.0).powi(2) + noise))
+            .collect())
+    }
+
+    /// Takes the all-mark symbol in `preceding` as the reference; the next chunk follows it.
+    fn prime(&self, preceding: &[f32]) {
+        self.reference
+            .set((!preceding.is_empty()).then(|| self.iq(preceding, 0)));
+        self.offset.set(preceding.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qpsk_gray_mapping_and_roundtrip_with_carrier_offset() {
+        let modem = QPSK::default();
+        // Each bit pair lands in the quadrant of the table, so neighbours differ in one bit
+        for (b0, b1, degrees) in [(false, false, 45.0), (false, true, -45.0), (true, true, -135.0), (true, false, 135.0)] {
+            let symbol: Vec<f32> = modem.gen_wave(b0, b1, 0).collect();
+            let (i, q) = modem.iq(&symbol, 0);
+            assert!((q.atan2(i).to_degrees() - degrees).abs() < 1.0, "{b0} {b1}");
+        }
+
+        // The acoustic path turns the carrier by 100° and the sound card runs it 2 Hz fast
+        let bits: Vec<bool> = (0..64).map(|i| (i * 5 + 1) % 7 < 3).collect();
+        let levels: Vec<(f32, f32)> = std::iter::once((true, true))
+            .chain(bits.chunks(2).map(|pair| (pair[0], pair[1])))
+            .map(|(b0, b1)| (QPSK::level(b0), QPSK::level(b1)))
+            .collect();
+        let omega = 2.0 * PI * 1_202.0 / SAMPLE_RATE as f32;
+        let signal: Vec<f32> = (0..levels.len() * 40)
+            .map(|n| {
+                let (i, q) = levels[n / 40];
+                let (sin, cos) = (omega * n as f32 + 100f32.to_radians()).sin_cos();
+                i * sin + q * cos
+            })
+            .collect();
+
+        let (reference, data) = signal.split_at(40);
+        modem.prime(reference);
+        let decoded: Vec<bool> = data
+            .chunks(40)
+            .flat_map(|chunk| modem.analyze_symbol(chunk).unwrap())
+            .map(|(mark_energy, space_energy)| mark_energy > space_energy)
+            .collect();
+        assert_eq!(decoded, bits);
+    }
+
+    #[test]
+    fn qpsk_roundtrip_with_a_carrier_off_the_symbol_grid() {
+        // 1500 Hz runs 2.5 cycles per symbol, so every other symbol starts half a cycle in
+        let bits: Vec<bool> = (0..64).map(|i| (i * 3 + 2) % 7 < 4).collect();
+        for carrier_freq in [1_500.0, 1_234.5] {
+            let modem = QPSK::new(SAMPLE_RATE, carrier_freq, 40);
+            // A run of the same symbol is one unbroken carrier
+            let omega = 2.0 * PI * carrier_freq / SAMPLE_RATE as f32;
+            for (n, sample) in modem.modulate(&[true; 8]).unwrap().into_iter().enumerate() {
+                let (sin, cos) = (omega * n as f32).sin_cos();
+                assert!((sample + FRAC_1_SQRT_2 * (sin + cos)).abs() < 1e-3, "{carrier_freq} Hz, sample {n}");
+            }
+
+            let mut data = vec![true, true];
+            data.extend(&bits);
+            let signal = modem.modulate(&data).unwrap();
+            assert_eq!(modem.demodulate(&signal).unwrap()[2..], bits, "{carrier_freq} Hz");
+
+            let (reference, data) = signal.split_at(40);
+            modem.prime(reference);
+            let decoded: Vec<bool> = data
+                .chunks(40)
+                .flat_map(|chunk| modem.analyze_symbol(chunk).unwrap())
+                .map(|(mark_energy, space_energy)| mark_energy > space_energy)
+                .collect();
+            assert_eq!(decoded, bits, "{carrier_freq} Hz, primed");
+        }
+    }
+}
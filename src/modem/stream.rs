@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+
+/// Recursive sine oscillator: a unit phasor rotated by a fixed step every sample.
+///
+/// Rotating costs four multiplies per sample instead of a `sin` call, and changing the
+/// frequency only changes the step, so the waveform never jumps (continuous phase).
+/// Rounding errors would slowly grow or shrink the phasor; every sample nudges it back
+/// to unit length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Oscillator {
+    sample_rate: u32,
+    phasor: (f32, f32), // (cos, sin) of the current phase
+    step: (f32, f32),   // (cos, sin) of the phase advance per sample
+}
+
+impl Oscillator {
+    /// A silent oscillator (0 Hz) at phase 0.
+    pub fn new(sample_rate: u32) -> Self {
+        Self { sample_rate, phasor: (1.0, 0.0), step: (1.0, 0.0) }
+    }
+
+    /// Changes the frequency from the next sample on, keeping the phase.
+    pub fn set_frequency(&mut self, frequency: f32) {
+        let omega = 2.0 * PI * frequency / self.sample_rate as f32;
+        self.step = (omega.cos(), omega.sin());
+    }
+
+    /// Back to phase 0, keeping the frequency.
+    pub fn reset(&mut self) {
+        self.phasor = (1.0, 0.0);
+    }
+
+    /// Current phase in radians, between -π and π.
+    pub fn phase(&self) -> f32 {
+        self.phasor.1.atan2(self.phasor.0)
+    }
+
+    /// Returns `sin` of the current phase, then advances it by one sample.
+    pub fn next_sample(&mut self) -> f32 {
+        let (re, im) = self.phasor;
+        let (c, s) = self.step;
+        let (re, im) = (re * c - im * s, re * s + im * c);
+        // First-order correction towards |phasor| = 1, cheaper than a square root
+        let gain = 1.5 - 0.5 * (re * re + im * im);
+        let sample = self.phasor.1;
+        self.phasor = (re * gain, im * gain);
+        sample
+    }
+}
+
+/// Modulator that produces its signal piece by piece, keeping its state between calls.
+///
+/// [`ModemTrait::modulate`](super::ModemTrait::modulate) needs the whole message up front and
+/// returns the whole signal. A stream modulator instead pulls bits as each symbol starts and
+/// writes into a buffer the caller owns, such as the one an audio output callback hands out,
+/// so transmissions can go on indefinitely in constant memory.
+pub trait StreamModulator {
+    /// Writes the signal into `out`, pulling the bits of every new symbol from `bits`.
+    ///
+    /// Returns how many samples were written: all of `out`, unless `bits` ran dry at a symbol
+    /// boundary. The next call then picks up there, with more bits, without a discontinuity.
+    /// A symbol only starts once all its bits are in: the bits of an incomplete one are held
+    /// back for the next call, until [`finish`](Self::finish) pads them.
+    fn fill(&mut self, bits: &mut dyn Iterator<Item = bool>, out: &mut [f32]) -> usize;
+
+    /// Ends the transmission: the bits held back are padded with 0s into a last symbol, like
+    /// `modulate` does, which the next `fill` writes out whatever bits it is given.
+    fn finish(&mut self);
+
+    /// Drops the symbol in progress and starts over as a new transmission.
+    fn reset(&mut self);
+
+    /// Ends the symbol in progress where the signal stands, keeping the phase: the next
+    /// `fill` starts a new symbol. RTTY's 1.5 stop bits are sent this way.
+    fn end_symbol(&mut self);
+
+    /// Turns a bit source into an iterator over the signal's samples.
+    fn samples<I>(self, bits: I) -> StreamSamples<Self, I>
+    where
+        Self: Sized,
+        I: Iterator<Item = bool>,
+    {
+        StreamSamples { modulator: self, bits }
+    }
+}
+
+/// Iterator over the samples of a [`StreamModulator`], see [`StreamModulator::samples`].
+#[derive(Debug, Clone)]
+pub struct StreamSamples<M, I> {
+    modulator: M,
+    bits: I,
+}
+
+impl<M: StreamModulator, I: Iterator<Item = bool>> Iterator for StreamSamples<M, I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut sample = [0.0];
+        if self.modulator.fill(&mut self.bits, &mut sample) == 0 {
+            // The bits are over, and so is the transmission
+            self.modulator.finish();
+            if self.modulator.fill(&mut std::iter::empty(), &mut sample) == 0 {
+                return None;
+            }
+        }
+        Some(sample[0])
+    }
+}
+
+/// Stream modulator for the tone-keyed modems (FSK, MFSK, OOK): every symbol value is a
+/// tone of its own frequency and amplitude, played on a single continuous-phase oscillator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneModulator {
+    oscillator: Oscillator,
+    tones: Vec<(f32, f32)>, // (frequency, amplitude) of each symbol value
+    bits_per_symbol: usize,
+    samples_per_symbol: usize,
+    amplitude: f32,     // Of the symbol in progress
+    remaining: usize,   // Samples left in the symbol in progress
+    pending: Vec<bool>, // Bits of the next symbol received so far
+}
+
+impl ToneModulator {
+    /// `tones[v]` is the `(frequency, amplitude)` sent for the symbol value `v`, whose bits
+    /// arrive MSB first.
+    ///
+    /// # Panics
+    /// If the number of tones is not a power of two, or below 2.
+    pub fn new(sample_rate: u32, tones: Vec<(f32, f32)>, samples_per_symbol: u32) -> Self {
+        assert!(
+            tones.len() >= 2 && tones.len().is_power_of_two(),
+            "a tone table needs a power of two of tones, got {}",
+            tones.len()
+        );
+        Self {
+            oscillator: Oscillator::new(sample_rate),
+            bits_per_symbol: tones.len().trailing_zeros() as usize,
+            tones,
+            samples_per_symbol: samples_per_symbol as usize,
+            amplitude: 0.0,
+            remaining: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Reads the next symbol from `bits` and tunes the oscillator to it. Without enough bits
+    /// for a whole symbol, keeps the ones read for later and returns `false`.
+    fn start_symbol(&mut self, bits: &mut dyn Iterator<Item = bool>) -> bool {
+        let missing = self.bits_per_symbol - self.pending.len();
+        self.pending.extend(bits.take(missing));
+        if self.pending.len() < self.bits_per_symbol {
+            return false;
+        }
+        self.play_pending();
+        true
+    }
+
+    /// Tunes the oscillator to the symbol of the pending bits, padded with 0s.
+    fn play_pending(&mut self) {
+        let padding = self.bits_per_symbol - self.pending.len();
+        let value = self.pending.drain(..).fold(0, |value, bit| (value << 1) | bit as usize);
+        let (frequency, amplitude) = self.tones[value << padding];
+        self.oscillator.set_frequency(frequency);
+        self.amplitude = amplitude;
+        self.remaining = self.samples_per_symbol;
+    }
+}
+
+impl StreamModulator for ToneModulator {
+    fn fill(&mut self, bits: &mut dyn Iterator<Item = bool>, out: &mut [f32]) -> usize {
+        let mut written = 0;
+        while written < out.len() {
+            if self.remaining == 0 && !self.start_symbol(bits) {
+                break;
+            }
+            let run = self.remaining.min(out.len() - written);
+            for sample in &mut out[written..written + run] {
+                *sample = self.amplitude * self.oscillator.next_sample();
+            }
+            written += run;
+            self.remaining -= run;
+        }
+        written
+    }
+
+    fn finish(&mut self) {
+        if !self.pending.is_empty() {
+            self.play_pending();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.oscillator.reset();
+        self.remaining = 0;
+        self.pending.clear();
+    }
+
+    fn end_symbol(&mut self) {
+        self.remaining = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::{FSK, ModemTrait};
+
+    #[test]
+    fn stream_matches_modulate_across_uneven_buffers() {
+        let modem = FSK::default();
+        let bits: Vec<bool> = (0..40).map(|i| (i * 3 + 1) % 5 < 2).collect();
+        let whole = modem.modulate(&bits).unwrap();
+
+        // Feed the bits in two bursts and read them out through awkward buffer sizes
+        let mut streamer = modem.streamer().unwrap();
+        let mut streamed = Vec::new();
+        let mut buffer = [0.0; 97];
+        for burst in bits.chunks(25) {
+            let mut burst = burst.iter().copied();
+            loop {
+                let written = streamer.fill(&mut burst, &mut buffer);
+                streamed.extend_from_slice(&buffer[..written]);
+                if written < buffer.len() { break; }
+            }
+        }
+        assert_eq!(streamed, whole);
+
+        // Two seconds of oscillation later the phasor is still of unit length; left uncorrected,
+        // rounding would have taken it about 1e-3 off by then
+        let mut oscillator = Oscillator::new(48_000);
+        oscillator.set_frequency(1_234.5);
+        for _ in 0..100_000 {
+            oscillator.next_sample();
+        }
+        let (re, im) = oscillator.phasor;
+        let length = (re * re + im * im).sqrt();
+        assert!((length - 1.0).abs() < 1e-4, "phasor length {length}");
+    }
+
+    #[test]
+    fn partial_symbols_wait_for_their_bits_until_the_transmission_ends() {
+        use crate::modem::MFSK;
+
+        let modem = MFSK::new(48_000, 1_000.0, 300.0, 16, 160);
+        let bits: Vec<bool> = (0..30).map(|i| (i * 7 + 3) % 11 < 5).collect();
+        let whole = modem.modulate(&bits).unwrap();
+
+        // A producer falling behind hands over 7 bits at a time, never a whole number of symbols
+        let mut streamer = modem.streamer().unwrap();
+        let mut streamed = Vec::new();
+        let mut buffer = [0.0; 1_000];
+        for burst in bits.chunks(7) {
+            let written = streamer.fill(&mut burst.iter().copied(), &mut buffer);
+            streamed.extend_from_slice(&buffer[..written]);
+        }
+        // Only the last two bits are padded, once the transmission is over
+        streamer.finish();
+        let written = streamer.fill(&mut std::iter::empty(), &mut buffer);
+        streamed.extend_from_slice(&buffer[..written]);
+        assert_eq!(streamed, whole);
+    }
+}
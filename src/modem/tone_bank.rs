@@ -0,0 +1,142 @@
+use std::f64::consts::PI;
+
+use rustfft::num_complex::Complex;
+
+/// Sliding DFT filter bank: the energy of a set of tones over every window of a signal.
+///
+/// A receiver hunting for symbol boundaries measures its tones at every candidate offset,
+/// and running [`goertzel`](super::goertzel) over each one costs a whole window of work per
+/// offset and tone. The bank instead slides each tone's DFT bin along the signal, one
+/// sample in and one out, so every new window costs O(1) per tone. The energies match
+/// `goertzel` over the same window.
+///
+/// Windows are indexed by their first sample. The signal can grow, change from some point
+/// on (an equalizer refreshing its output) or lose its oldest samples, and the bank follows
+/// with [`update`](Self::update), [`invalidate`](Self::invalidate) and [`drain`](Self::drain).
+/// Every `update` starts from an exact DFT, so rounding errors cannot pile up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneBank {
+    window: usize,
+    rotations: Vec<Complex<f64>>, // e^{jω} per tone: slides a bin one sample along
+    tails: Vec<Complex<f64>>,     // e^{-jω(window - 1)} per tone: weight of the newest sample
+    energies: Vec<f32>,           // [window start][tone], from the first buffered sample on
+}
+
+impl ToneBank {
+    /// # Panics
+    /// If the window is empty.
+    pub fn new(tones: &[f32], sample_rate: u32, window: usize) -> Self {
+        assert!(window > 0, "a tone bank needs a window of at least one sample");
+        let omegas: Vec<f64> = tones.iter().map(|&f| 2.0 * PI * f as f64 / sample_rate as f64).collect();
+        Self {
+            window,
+            rotations: omegas.iter().map(|&w| Complex::from_polar(1.0, w)).collect(),
+            tails: omegas.iter().map(|&w| Complex::from_polar(1.0, -w * (window - 1) as f64)).collect(),
+            energies: Vec::new(),
+        }
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Number of windows measured so far.
+    fn measured(&self) -> usize {
+        self.energies.len() / self.rotations.len().max(1)
+    }
+
+    /// Measures every window of `signal` not measured yet.
+    pub fn update(&mut self, signal: &[f32]) {
+        let tones = self.rotations.len();
+        let start = self.measured();
+        let Some(windows) = (signal.len() + 1).checked_sub(start + self.window).filter(|&w| w > 0) else { return };
+        self.energies.reserve(windows * tones);
+
+        // Exact DFT of the first new window, then slide
+        let mut bins: Vec<Complex<f64>> = self
+            .rotations
+            .iter()
+            .map(|rotation| {
+                let step = rotation.conj();
+                let mut weight = Complex::new(1.0, 0.0);
+                signal[start..start + self.window].iter().fold(Complex::new(0.0, 0.0), |acc, &x| {
+                    let acc = acc + weight * x as f64;
+                    weight *= step;
+                    acc
+                })
+            })
+            .collect();
+        // Over digital silence the slid bins would keep rounding residue where `goertzel` reads
+        // exactly 0, and a receiver learning its noise floor there would take it at face value
+        let mut sounding = signal[start..start + self.window].iter().filter(|&&x| x != 0.0).count();
+        for n in start..start + windows {
+            if n > start {
+                let (outgoing, incoming) = (signal[n - 1] as f64, signal[n + self.window - 1] as f64);
+                sounding = sounding + (incoming != 0.0) as usize - (outgoing != 0.0) as usize;
+                for ((bin, rotation), tail) in bins.iter_mut().zip(&self.rotations).zip(&self.tails) {
+                    *bin = match sounding {
+                        0 => Complex::new(0.0, 0.0),
+                        _ => rotation * (*bin - outgoing) + tail * incoming,
+                    };
+                }
+            }
+            self.energies.extend(bins.iter().map(|bin| bin.norm_sqr() as f32));
+        }
+    }
+
+    /// Forgets the windows touching `signal[from..]`, which changed; `update` measures them again.
+    pub fn invalidate(&mut self, from: usize) {
+        let keep = (from + 1).saturating_sub(self.window).min(self.measured());
+        self.energies.truncate(keep * self.rotations.len());
+    }
+
+    /// Forgets the first `len` windows, after the signal dropped its first `len` samples.
+    pub fn drain(&mut self, len: usize) {
+        let len = (len * self.rotations.len()).min(self.energies.len());
+        self.energies.drain(..len);
+    }
+
+    /// Energy of every tone over `signal[start..start + window]`, if measured.
+    pub fn energies(&self, start: usize) -> Option<&[f32]> {
+        let tones = self.rotations.len();
+        self.energies.get(start * tones..(start + 1) * tones)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::goertzel;
+
+    #[test]
+    fn sliding_energies_match_goertzel() {
+        let tones = [1_200.0, 2_200.0, 2_250.0];
+        let signal: Vec<f32> = (0..2_000)
+            .map(|n| (0.13 * n as f32).sin() + 0.5 * (0.29 * n as f32).cos() + ((n * 7919 % 1000) as f32 / 1000.0 - 0.5))
+            .collect();
+        let mut bank = ToneBank::new(&tones, 48_000, 160);
+
+        // Grow the signal in uneven steps, rewrite its middle, then drop its start
+        bank.update(&signal[..500]);
+        bank.update(&signal[..1_337]);
+        bank.invalidate(900);
+        bank.update(&signal);
+        bank.drain(300);
+        let signal = &signal[300..];
+
+        for start in (0..=signal.len() - 160).step_by(7) {
+            for (&tone, &energy) in tones.iter().zip(bank.energies(start).unwrap()) {
+                let exact = goertzel(&signal[start..start + 160], tone, 48_000);
+                assert!((energy - exact).abs() <= 1e-3 * exact.max(1.0), "{tone} Hz at {start}: {energy} vs {exact}");
+            }
+        }
+        assert!(bank.energies(signal.len() - 159).is_none());
+
+        // A stretch of silence reads as exactly none of any tone, like it does to `goertzel`
+        let mut silenced = signal.to_vec();
+        silenced[1_000..1_200].fill(0.0);
+        let mut quiet = ToneBank::new(&tones, 48_000, 160);
+        quiet.update(&silenced);
+        assert!(quiet.energies(1_000).unwrap().iter().all(|&energy| energy == 0.0));
+    }
+}
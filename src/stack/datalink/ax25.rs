@@ -0,0 +1,240 @@
+// * AX.25 UI frames over Bell 202 AFSK, as spoken by APRS and packet-radio TNCs
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+use dev_utils::{debug, info};
+
+use super::hdlc::{HDLC_FLAG, Hdlc, HdlcCodec};
+use super::{CodecTrait, FrameKind};
+use crate::modem::FSK;
+
+/// Control field of an unnumbered information (UI) frame.
+const CONTROL_UI: u8 = 0x03;
+/// Protocol identifier for "no layer 3 protocol", what APRS uses.
+pub const PID_NO_LAYER3: u8 = 0xF0;
+
+/// AX.25 station address: a callsign of up to six characters plus an SSID (0-15).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ax25Address {
+    pub callsign: String,
+    pub ssid: u8,
+}
+
+impl Ax25Address {
+    pub fn new(callsign: &str, ssid: u8) -> Self {
+        Self { callsign: callsign.to_ascii_uppercase(), ssid: ssid & 0x0F }
+    }
+
+    /// Encodes the address field: the callsign shifted left by one, space padded, then the
+    /// SSID byte with its command/has-been-repeated bit and the end-of-address bit.
+    fn to_bytes(&self, high_bit: bool, last: bool) -> [u8; 7] {
+        let mut bytes = [b' ' << 1; 7];
+        for (byte, c) in bytes.iter_mut().zip(self.callsign.bytes().take(6)) {
+            *byte = c << 1;
+        }
+        bytes[6] = 0x60 | (self.ssid << 1) | ((high_bit as u8) << 7) | last as u8;
+        bytes
+    }
+
+    /// Decodes an address field, returning it with its end-of-address bit.
+    fn from_bytes(bytes: &[u8]) -> (Self, bool) {
+        let callsign: String = bytes[..6].iter().map(|&b| (b >> 1) as char).collect();
+        let address = Self { callsign: callsign.trim_end().to_string(), ssid: (bytes[6] >> 1) & 0x0F };
+        (address, bytes[6] & 1 == 1)
+    }
+}
+
+impl Display for Ax25Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.ssid {
+            0 => write!(f, "{}", self.callsign),
+            ssid => write!(f, "{}-{}", self.callsign, ssid),
+        }
+    }
+}
+
+/// AX.25 UI (unnumbered information) frame, the connectionless frame APRS is built on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ax25Frame {
+    pub destination: Ax25Address,
+    pub source: Ax25Address,
+    pub digipeaters: Vec<Ax25Address>, // At most 8
+    pub pid: u8,
+    pub info: Vec<u8>,
+}
+
+impl Ax25Frame {
+    /// Creates a UI frame without digipeaters and without a layer 3 protocol.
+    pub fn ui(destination: Ax25Address, source: Ax25Address, info: &[u8]) -> Self {
+        Self { destination, source, digipeaters: Vec::new(), pid: PID_NO_LAYER3, info: info.to_vec() }
+    }
+
+    /// Serializes the frame (without FCS, which the HDLC layer adds).
+    ///
+    /// The frame is sent as a command: the destination carries the command bit, the source
+    /// does not (AX.25 v2.0).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + 7 * self.digipeaters.len() + self.info.len());
+        bytes.extend(self.destination.to_bytes(true, false));
+        bytes.extend(self.source.to_bytes(false, self.digipeaters.is_empty()));
+        for (i, digi) in self.digipeaters.iter().enumerate() {
+            bytes.extend(digi.to_bytes(false, i + 1 == self.digipeaters.len()));
+        }
+        bytes.extend([CONTROL_UI, self.pid]);
+        bytes.extend(&self.info);
+        bytes
+    }
+
+    /// Parses a frame received from the HDLC layer (FCS already checked and removed).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut addresses = Vec::new();
+        let mut pos = 0;
+        loop {
+            let field = bytes.get(pos..pos + 7).ok_or("AX.25 address field is truncated")?;
+            let (address, last) = Ax25Address::from_bytes(field);
+            addresses.push(address);
+            pos += 7;
+            if last { break; }
+            if addresses.len() == 10 { return Err("AX.25 frame has more than 8 digipeaters".into()); }
+        }
+        if addresses.len() < 2 {
+            return Err("AX.25 frame needs a destination and a source".into());
+        }
+
+        match bytes.get(pos..pos + 2) {
+            Some(&[CONTROL_UI, pid]) => {
+                let mut addresses = addresses.into_iter();
+                Ok(Self {
+                    destination: addresses.next().unwrap(),
+                    source: addresses.next().unwrap(),
+                    digipeaters: addresses.collect(),
+                    pid,
+                    info: bytes[pos + 2..].to_vec(),
+                })
+            }
+            Some(&[control, _]) => Err(format!("Unsupported AX.25 control field 0x{control:02X}, only UI frames are handled").into()),
+            _ => Err("AX.25 frame is missing its control and PID fields".into()),
+        }
+    }
+}
+
+impl Display for Ax25Frame {
+    /// TNC2 monitor format, e.g. `N0CALL-7>APRS,WIDE1-1:payload`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}>{}", self.source, self.destination)?;
+        for digi in &self.digipeaters {
+            write!(f, ",{digi}")?;
+        }
+        write!(f, ":{}", String::from_utf8_lossy(&self.info))
+    }
+}
+
+/// Bell 202 AFSK codec producing and decoding AX.25 UI frames.
+///
+/// `encode` sends the payload as the information field of a UI frame from `source` to
+/// `destination`, HDLC framed (flags, bit stuffing and FCS-16), NRZI
+/// coded and modulated with [`FSK::bell202`]. `decode` returns the information field of
+/// every valid frame heard; [`Ax25Codec::decode_frames`] gives the full frames, including
+/// ones sent by other stations.
+///
+/// The link itself is an NRZI coded [`HdlcCodec`], whose receiver runs several bit clocks
+/// spread over one bit period and lets the FCS decide which of them got each frame right.
+pub struct Ax25Codec {
+    link: HdlcCodec,
+    source: Ax25Address,
+    destination: Ax25Address,
+    digipeaters: Vec<Ax25Address>,
+}
+
+impl Ax25Codec {
+    pub fn new(sample_rate: u32, source: Ax25Address, destination: Ax25Address) -> Self {
+        let hdlc = Hdlc::new(FrameKind::BitOriented { flag: HDLC_FLAG }).expect("0x7E is the HDLC flag");
+        let link = HdlcCodec::new(Box::new(FSK::bell202(sample_rate)), sample_rate, 1_200.0, hdlc).with_nrzi(true);
+        Self { link, source, destination, digipeaters: Vec::new() }
+    }
+
+    /// Sets the digipeater path of the frames we send (e.g. `WIDE1-1`).
+    pub fn with_digipeaters(mut self, digipeaters: Vec<Ax25Address>) -> Self {
+        self.digipeaters = digipeaters;
+        self
+    }
+
+    /// Modulates a complete AX.25 frame.
+    pub fn encode_frame(&self, frame: &Ax25Frame) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.link.encode_frame(&frame.to_bytes())
+    }
+
+    /// Feeds audio to the receiver, returning every new frame with a valid FCS.
+    pub fn decode_frames(&mut self, samples: &[f32]) -> Result<Vec<Ax25Frame>, Box<dyn Error>> {
+        let mut frames = Vec::new();
+        for bytes in self.link.decode_frames(samples)? {
+            match Ax25Frame::from_bytes(&bytes) {
+                Ok(frame) => {
+                    info!("AX.25 FRAME: {}", frame);
+                    frames.push(frame);
+                }
+                Err(e) => debug!("Dropping HDLC frame: {}", e),
+            }
+        }
+        Ok(frames)
+    }
+}
+
+impl CodecTrait for Ax25Codec {
+    fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut frame = Ax25Frame::ui(self.destination.clone(), self.source.clone(), payload);
+        frame.digipeaters = self.digipeaters.clone();
+        self.encode_frame(&frame)
+    }
+
+    fn decode(&mut self, samples: &[f32]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let info: Vec<u8> = self.decode_frames(samples)?.into_iter().flat_map(|frame| frame.info).collect();
+        Ok(if info.is_empty() { None } else { Some(info) })
+    }
+
+    fn reset_state(&mut self) {
+        self.link.reset_state();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ax25_ui_frame_roundtrip() {
+        let tx = Ax25Codec::new(48_000, Ax25Address::new("n0call", 7), Ax25Address::new("APRS", 0))
+            .with_digipeaters(vec![Ax25Address::new("WIDE1", 1)]);
+        let mut rx = Ax25Codec::new(48_000, Ax25Address::new("N0CALL", 1), Ax25Address::new("APRS", 0));
+
+        let info = b"!4903.50N/07201.75W-Test 001234";
+        let mut signal = vec![0.0; 1_234];
+        signal.extend(tx.encode(info).unwrap());
+        signal.extend(vec![0.0; 2_000]);
+
+        let frames: Vec<Ax25Frame> = signal.chunks(1_000).flat_map(|c| rx.decode_frames(c).unwrap()).collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].to_string(), format!("N0CALL-7>APRS,WIDE1-1:{}", String::from_utf8_lossy(info)));
+        // Destination "APRS" on the wire: shifted ASCII, space padded, command bit set
+        assert_eq!(&frames[0].to_bytes()[..7], &[0x82, 0xA0, 0xA4, 0xA6, 0x40, 0x40, 0xE0]);
+    }
+
+    #[test]
+    fn reset_state_drops_the_frame_in_progress() {
+        let tx = Ax25Codec::new(48_000, Ax25Address::new("N0CALL", 7), Ax25Address::new("APRS", 0));
+        let mut rx = Ax25Codec::new(48_000, Ax25Address::new("N0CALL", 1), Ax25Address::new("APRS", 0));
+
+        let mut signal = vec![0.0; 1_234];
+        signal.extend(tx.encode(b"cut in half").unwrap());
+        signal.extend(vec![0.0; 2_000]);
+        let (first, second) = signal.split_at(signal.len() / 2);
+
+        assert!(rx.decode_frames(first).unwrap().is_empty());
+        rx.reset_state();
+        assert!(rx.decode_frames(second).unwrap().is_empty());
+
+        // The next whole frame still gets through
+        assert_eq!(rx.decode(&signal).unwrap(), Some(b"cut in half".to_vec()));
+    }
+}
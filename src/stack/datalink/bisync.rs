@@ -0,0 +1,169 @@
+// * BISYNC (IBM Binary Synchronous Communications) transparent text framing
+
+use std::error::Error;
+
+use super::FrameKind;
+use super::framed::crc16;
+
+/// Data link escape: makes the control character after it count.
+pub const DLE: u8 = 0x10;
+/// Start of text.
+pub const STX: u8 = 0x02;
+/// End of text.
+pub const ETX: u8 = 0x03;
+/// Pad sent after the block check, so the line settles past its last bit.
+const PAD: u8 = 0xFF;
+
+/// BISYNC framer, sending data as transparent text blocks:
+/// `SYN SYN DLE STX <text> DLE ETX <BCC> PAD`.
+///
+/// Only `DLE STX` and `DLE ETX` delimit text, so any byte may appear in it; a DLE in the
+/// data is sent twice. The block check (BCC) is the CRC-16 of the text and the ETX, low
+/// byte first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bisync {
+    sync: u8, // SYN character: 0x16 in ASCII, 0x32 in EBCDIC
+}
+
+impl Bisync {
+    /// Creates a framer for the given frame kind.
+    ///
+    /// Only [`FrameKind::BySync`] frames are BISYNC blocks, and their sync character cannot
+    /// be one of the text delimiters.
+    pub fn new(kind: FrameKind) -> Result<Self, Box<dyn Error>> {
+        match kind {
+            FrameKind::BySync { sync } if [DLE, STX, ETX].contains(&sync) => {
+                Err(format!("0x{sync:02X} delimits BISYNC text and cannot be the sync character").into())
+            }
+            FrameKind::BySync { sync } => Ok(Self { sync }),
+            other => Err(format!("{other:?} is not a BISYNC frame kind").into()),
+        }
+    }
+
+    /// Frames `data` as one transparent text block.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![self.sync, self.sync, DLE, STX];
+        for &byte in data {
+            if byte == DLE {
+                bytes.push(DLE);
+            }
+            bytes.push(byte);
+        }
+        bytes.extend([DLE, ETX]);
+        bytes.extend(Self::bcc(data).to_le_bytes());
+        bytes.push(PAD);
+        bytes
+    }
+
+    /// Block check of `text`: the CRC-16 of the text and its ETX.
+    fn bcc(text: &[u8]) -> u16 {
+        crc16(&[text, &[ETX]].concat())
+    }
+
+    /// Creates a receiver for blocks sent by this framer.
+    pub fn deframer(&self) -> BisyncDeframer {
+        BisyncDeframer { sync: self.sync, state: BisyncState::Hunt { syncs: 0 }, text: Vec::new() }
+    }
+}
+
+/// Where the receiver is within a block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BisyncState {
+    /// Looking for two sync characters, then `DLE STX`.
+    Hunt { syncs: usize },
+    /// Seen the sync characters and the DLE opening the text.
+    Start,
+    /// Inside the text; `escaped` after a DLE.
+    Text { escaped: bool },
+    /// Collecting the block check, `first` byte already received.
+    Bcc { first: Option<u8> },
+}
+
+/// Byte-by-byte BISYNC receiver.
+///
+/// Hunts for the sync characters, undoes the DLE doubling and only releases blocks whose
+/// BCC checks out. A `DLE STX` in the text starts the block over, as when the `DLE ETX`
+/// closing the last one was lost. Any other control character after a DLE in the text aborts
+/// the block, except `DLE SYN`, the transparent idle fill, which is skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BisyncDeframer {
+    sync: u8,
+    state: BisyncState,
+    text: Vec<u8>, // Text received since `DLE STX`
+}
+
+impl BisyncDeframer {
+    /// Longest text kept before giving up on a missing `DLE ETX`.
+    const MAX_FRAME_LEN: usize = 1024;
+
+    /// Feeds one received byte, returning a block's text when one completes.
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        self.state = match self.state {
+            BisyncState::Hunt { syncs } if byte == self.sync => BisyncState::Hunt { syncs: syncs + 1 },
+            BisyncState::Hunt { syncs } if syncs >= 2 && byte == DLE => BisyncState::Start,
+            BisyncState::Start if byte == STX => {
+                self.text.clear();
+                BisyncState::Text { escaped: false }
+            }
+            BisyncState::Text { escaped: false } if byte == DLE => BisyncState::Text { escaped: true },
+            BisyncState::Text { escaped: false } if self.text.len() < Self::MAX_FRAME_LEN => {
+                self.text.push(byte);
+                BisyncState::Text { escaped: false }
+            }
+            BisyncState::Text { escaped: true } => match byte {
+                DLE => {
+                    self.text.push(DLE);
+                    BisyncState::Text { escaped: false }
+                }
+                ETX => BisyncState::Bcc { first: None },
+                STX => {
+                    self.text.clear();
+                    BisyncState::Text { escaped: false }
+                }
+                _ if byte == self.sync => BisyncState::Text { escaped: false },
+                _ => BisyncState::Hunt { syncs: 0 },
+            },
+            BisyncState::Bcc { first: None } => BisyncState::Bcc { first: Some(byte) },
+            BisyncState::Bcc { first: Some(low) } => {
+                self.state = BisyncState::Hunt { syncs: 0 };
+                let text = std::mem::take(&mut self.text);
+                return (Bisync::bcc(&text) == u16::from_le_bytes([low, byte])).then_some(text);
+            }
+            _ => BisyncState::Hunt { syncs: 0 },
+        };
+        None
+    }
+
+    /// Drops the block in progress.
+    pub fn reset(&mut self) {
+        self.state = BisyncState::Hunt { syncs: 0 };
+        self.text.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bisync_text_is_transparent() {
+        let bisync = Bisync::new(FrameKind::BySync { sync: 0x16 }).unwrap();
+        let data = [0x16, 0x16, DLE, STX, DLE, ETX, 0x00, 0xFF];
+        let block = bisync.encode(&data);
+        // Every DLE in the text doubled
+        assert_eq!(&block[4..14], &[0x16, 0x16, DLE, DLE, STX, DLE, DLE, ETX, 0x00, 0xFF]);
+
+        // Idle fill inside the text is skipped, and a damaged block is dropped
+        let mut stream = block[..6].to_vec();
+        stream.extend([DLE, 0x16]);
+        stream.extend(&block[6..]);
+        let mut damaged = block.clone();
+        damaged[9] ^= 0x40;
+        stream.extend(damaged);
+
+        let mut deframer = bisync.deframer();
+        let blocks: Vec<Vec<u8>> = stream.into_iter().filter_map(|b| deframer.push(b)).collect();
+        assert_eq!(blocks, vec![data.to_vec()]);
+        assert!(Bisync::new(FrameKind::BySync { sync: DLE }).is_err());
+    }
+}
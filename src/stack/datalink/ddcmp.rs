@@ -0,0 +1,153 @@
+// * DDCMP (DEC Digital Data Communications Message Protocol) count-based message framing
+
+use std::error::Error;
+
+use super::FrameKind;
+use super::framed::crc16;
+
+/// Class byte of DDCMP data messages (SOH).
+pub const DDCMP_SOH: u8 = 0x81;
+/// Station address of a point-to-point link.
+const POINT_TO_POINT: u8 = 1;
+
+/// DDCMP framer: a fixed-size header says how long the data is, so the data itself needs no
+/// delimiters or escapes.
+///
+/// The header is the class byte, a 14-bit count (low byte first) with the two flag bits,
+/// the response and sequence numbers and the station address, followed by its own CRC-16.
+/// The data follows with a second CRC-16. A damaged count cannot send the receiver off
+/// reading the wrong number of bytes, since the header CRC catches it first.
+///
+/// Only the message framing is implemented: sequence and response numbers go out as 0,
+/// there are no acknowledgements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ddcmp {
+    class: u8, // First header byte, what the receiver hunts for
+}
+
+impl Default for Ddcmp {
+    fn default() -> Self {
+        Self { class: DDCMP_SOH }
+    }
+}
+
+impl Ddcmp {
+    /// Header bytes, CRC included.
+    const HEADER_LEN: usize = 8;
+    /// Longest message data sent or accepted. The 14-bit count could say up to 16383 bytes,
+    /// but the receiver keeps its buffer to this.
+    pub const MAX_FRAME_LEN: usize = 1024;
+
+    /// Creates a framer for the given frame kind, whose control byte opens every message.
+    pub fn new(kind: FrameKind) -> Result<Self, Box<dyn Error>> {
+        match kind {
+            FrameKind::DDCMP { control } => Ok(Self { class: control }),
+            other => Err(format!("{other:?} is not a DDCMP frame kind").into()),
+        }
+    }
+
+    /// Frames `data` as one data message, of at most [`MAX_FRAME_LEN`](Self::MAX_FRAME_LEN) bytes.
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        if data.len() > Self::MAX_FRAME_LEN {
+            return Err(format!("DDCMP messages hold at most {} bytes, got {}", Self::MAX_FRAME_LEN, data.len()).into());
+        }
+        let count = data.len() as u16;
+        let header = [self.class, count as u8, (count >> 8) as u8, 0, 0, POINT_TO_POINT];
+        let mut bytes = Vec::with_capacity(Self::HEADER_LEN + data.len() + 2);
+        bytes.extend(header);
+        bytes.extend(crc16(&header).to_le_bytes());
+        bytes.extend(data);
+        bytes.extend(crc16(data).to_le_bytes());
+        Ok(bytes)
+    }
+
+    /// Creates a receiver for messages sent by this framer.
+    pub fn deframer(&self) -> DdcmpDeframer {
+        DdcmpDeframer { class: self.class, bytes: Vec::new() }
+    }
+}
+
+/// Byte-by-byte DDCMP receiver.
+///
+/// Hunts for the class byte and trusts a header only once its CRC checks out; a damaged
+/// header makes it hunt again from the next byte. The data is then read by count and only
+/// released if its own CRC checks out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DdcmpDeframer {
+    class: u8,
+    bytes: Vec<u8>, // Received bytes from the class byte of a candidate header on
+}
+
+impl DdcmpDeframer {
+    /// Feeds one received byte, returning a message's data when one completes.
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        self.bytes.push(byte);
+        loop {
+            let Some(start) = self.bytes.iter().position(|&b| b == self.class) else {
+                self.bytes.clear();
+                return None;
+            };
+            self.bytes.drain(..start);
+            let header = self.bytes.get(..Ddcmp::HEADER_LEN)?;
+
+            let count = u16::from_le_bytes([header[1], header[2] & 0x3F]) as usize;
+            if crc16(&header[..6]) != u16::from_le_bytes([header[6], header[7]]) || count > Ddcmp::MAX_FRAME_LEN {
+                // Not a header after all: hunt from the next byte
+                self.bytes.drain(..1);
+                continue;
+            }
+
+            let end = Ddcmp::HEADER_LEN + count + 2;
+            if self.bytes.len() < end {
+                return None;
+            }
+            let message: Vec<u8> = self.bytes.drain(..end).collect();
+            let (data, trailer) = message[Ddcmp::HEADER_LEN..].split_at(count);
+            return (crc16(data) == u16::from_le_bytes([trailer[0], trailer[1]])).then(|| data.to_vec());
+        }
+    }
+
+    /// Drops the message in progress.
+    pub fn reset(&mut self) {
+        self.bytes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddcmp_reads_messages_by_count() {
+        let ddcmp = Ddcmp::new(FrameKind::DDCMP { control: DDCMP_SOH }).unwrap();
+        // The class byte inside the data needs no escaping
+        let data = [DDCMP_SOH, 0x05, 0x00, DDCMP_SOH];
+        let message = ddcmp.encode(&data).unwrap();
+        assert_eq!(&message[..3], &[DDCMP_SOH, 4, 0]);
+
+        // Noise before a message, a damaged header and damaged data are all skipped
+        let mut stream = vec![0x42, DDCMP_SOH, 0x00];
+        let mut bad_header = message.clone();
+        bad_header[1] = 200;
+        stream.extend(bad_header);
+        let mut bad_data = message.clone();
+        bad_data[9] ^= 0x01;
+        stream.extend(bad_data);
+        stream.extend(&message);
+
+        let mut deframer = ddcmp.deframer();
+        let messages: Vec<Vec<u8>> = stream.into_iter().filter_map(|b| deframer.push(b)).collect();
+        assert_eq!(messages, vec![data.to_vec()]);
+    }
+
+    #[test]
+    fn messages_longer_than_the_receiver_takes_are_refused() {
+        let ddcmp = Ddcmp::default();
+        assert!(ddcmp.encode(&[0x55; Ddcmp::MAX_FRAME_LEN + 1]).is_err());
+
+        let data = [0x55; Ddcmp::MAX_FRAME_LEN];
+        let mut deframer = ddcmp.deframer();
+        let messages: Vec<Vec<u8>> = ddcmp.encode(&data).unwrap().into_iter().filter_map(|byte| deframer.push(byte)).collect();
+        assert_eq!(messages, vec![data.to_vec()]);
+    }
+}
@@ -0,0 +1,207 @@
+// * Byte-level framing over a byte codec, selected by frame kind
+
+use std::error::Error;
+
+use crc::{CRC_16_ARC, Crc};
+
+use super::bisync::{Bisync, BisyncDeframer};
+use super::ddcmp::{Ddcmp, DdcmpDeframer};
+use super::ppp::{AsyncPpp, AsyncPppDeframer};
+use super::{CodecTrait, FrameKind};
+
+/// CRC-16 (ARC) used as the block check of BISYNC and DDCMP.
+const CRC16: Crc<u16> = Crc::<u16>::new(&CRC_16_ARC);
+
+/// Computes the CRC-16 BISYNC and DDCMP check their blocks with.
+pub fn crc16(data: &[u8]) -> u16 {
+    CRC16.checksum(data)
+}
+
+/// A framing that works on whole bytes, so it can run over the UART characters of
+/// [`SonarCodec`](super::SonarCodec).
+///
+/// Async PPP and BISYNC find frames by their delimiters and escape them inside the data;
+/// DDCMP finds them by a header holding the length, so its data goes out as is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteFraming {
+    AsyncPpp(AsyncPpp),
+    Bisync(Bisync),
+    Ddcmp(Ddcmp),
+}
+
+impl ByteFraming {
+    /// The framing of a byte-oriented frame kind.
+    ///
+    /// [`FrameKind::BitOriented`] frames are stuffed bit by bit, below the bytes; see
+    /// [`HdlcCodec`](super::HdlcCodec).
+    pub fn new(kind: FrameKind) -> Result<Self, Box<dyn Error>> {
+        match kind {
+            FrameKind::AsyncPPP { .. } => AsyncPpp::new(kind).map(Self::AsyncPpp),
+            FrameKind::BySync { .. } => Bisync::new(kind).map(Self::Bisync),
+            FrameKind::DDCMP { .. } => Ddcmp::new(kind).map(Self::Ddcmp),
+            FrameKind::BitOriented { .. } => Err("Bit-oriented frames are not byte framed, use HdlcCodec".into()),
+        }
+    }
+
+    /// Frames `data` as one frame. Fails if the framing cannot hold that much data.
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self {
+            ByteFraming::AsyncPpp(ppp) => Ok(ppp.encode(data)),
+            ByteFraming::Bisync(bisync) => Ok(bisync.encode(data)),
+            ByteFraming::Ddcmp(ddcmp) => ddcmp.encode(data),
+        }
+    }
+
+    /// Creates a receiver for frames sent with this framing.
+    pub fn deframer(&self) -> ByteDeframer {
+        match self {
+            ByteFraming::AsyncPpp(ppp) => ByteDeframer::AsyncPpp(ppp.deframer()),
+            ByteFraming::Bisync(bisync) => ByteDeframer::Bisync(bisync.deframer()),
+            ByteFraming::Ddcmp(ddcmp) => ByteDeframer::Ddcmp(ddcmp.deframer()),
+        }
+    }
+}
+
+/// Byte-by-byte receiver of a [`ByteFraming`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ByteDeframer {
+    AsyncPpp(AsyncPppDeframer),
+    Bisync(BisyncDeframer),
+    Ddcmp(DdcmpDeframer),
+}
+
+impl ByteDeframer {
+    /// Feeds one received byte, returning a frame's data when one completes and checks out.
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match self {
+            ByteDeframer::AsyncPpp(deframer) => deframer.push(byte),
+            ByteDeframer::Bisync(deframer) => deframer.push(byte),
+            ByteDeframer::Ddcmp(deframer) => deframer.push(byte),
+        }
+    }
+
+    /// Drops the frame in progress.
+    pub fn reset(&mut self) {
+        match self {
+            ByteDeframer::AsyncPpp(deframer) => deframer.reset(),
+            ByteDeframer::Bisync(deframer) => deframer.reset(),
+            ByteDeframer::Ddcmp(deframer) => deframer.reset(),
+        }
+    }
+}
+
+/// Sends payloads as frames of a [`ByteFraming`] over a byte codec, such as the UART
+/// characters of [`SonarCodec`](super::SonarCodec).
+///
+/// The receiver gets back exactly the payloads sent, binary or not, and nothing from frames
+/// that arrived damaged. Payloads longer than `max_frame_len` are split over several frames.
+///
+/// This is how a [`FrameKind`] is picked for a `SonarCodec` link: its config only describes
+/// the characters, so wrap it as `FramedCodec::new(codec, ByteFraming::new(kind)?)`.
+pub struct FramedCodec<C: CodecTrait> {
+    codec: C,
+    framing: ByteFraming,
+    deframer: ByteDeframer,
+    max_frame_len: usize,
+}
+
+impl<C: CodecTrait> FramedCodec<C> {
+    pub fn new(codec: C, framing: ByteFraming) -> Self {
+        Self { codec, framing, deframer: framing.deframer(), max_frame_len: 256 }
+    }
+
+    /// Sets the longest frame `encode` sends (1 to 1022 bytes); longer payloads are split.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len.clamp(1, 1022);
+        self
+    }
+
+    /// The byte codec the frames travel over.
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Feeds audio to the receiver, returning every new frame that checks out.
+    pub fn decode_frames(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let bytes = self.codec.decode(samples)?.unwrap_or_default();
+        Ok(bytes.into_iter().filter_map(|byte| self.deframer.push(byte)).collect())
+    }
+}
+
+impl<C: CodecTrait> CodecTrait for FramedCodec<C> {
+    fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut bytes = Vec::new();
+        for chunk in payload.chunks(self.max_frame_len) {
+            bytes.extend(self.framing.encode(chunk)?);
+        }
+        self.codec.encode(&bytes)
+    }
+
+    fn decode(&mut self, samples: &[f32]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let payload: Vec<u8> = self.decode_frames(samples)?.concat();
+        Ok(if payload.is_empty() { None } else { Some(payload) })
+    }
+
+    /// Resets the codec below and drops the frame in progress.
+    fn reset_state(&mut self) {
+        self.codec.reset_state();
+        self.deframer.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::FSK;
+    use crate::stack::datalink::{SonarCodec, SonarCodecConfig};
+
+    const KINDS: [FrameKind; 3] = [
+        FrameKind::AsyncPPP { start_delim: 0x7E, end_delim: 0x7E },
+        FrameKind::BySync { sync: 0x16 },
+        FrameKind::DDCMP { control: 0x81 },
+    ];
+
+    #[test]
+    fn every_byte_framing_recovers_after_a_damaged_frame() {
+        let frames: [&[u8]; 3] = [b"\x7E\x7D\x10\x02\x03\x16\x81 first", b"second", b"third \x00\xFF"];
+        for kind in KINDS {
+            let framing = ByteFraming::new(kind).unwrap();
+            let stream: Vec<u8> = frames.iter().flat_map(|frame| framing.encode(frame).unwrap()).collect();
+            let second = framing.encode(frames[0]).unwrap().len();
+            let third = second + framing.encode(frames[1]).unwrap().len();
+            // A burst of errors in the middle of the second frame, then one over its end,
+            // where BISYNC's DLE ETX and PPP's closing flag are
+            for burst in [second + 3..second + 6, third - 5..third] {
+                let mut stream = stream.clone();
+                for byte in &mut stream[burst.clone()] {
+                    *byte ^= 0x5A;
+                }
+
+                let mut deframer = framing.deframer();
+                let received: Vec<Vec<u8>> = stream.into_iter().filter_map(|b| deframer.push(b)).collect();
+                assert_eq!(received, vec![frames[0].to_vec(), frames[2].to_vec()], "{kind:?} {burst:?}");
+            }
+        }
+        assert!(ByteFraming::new(FrameKind::default()).is_err());
+    }
+
+    #[test]
+    fn framed_codec_carries_binary_payloads_over_uart_characters() {
+        let payload = [0x00, b'\n', 0x7E, 0x7D, 0x10, 0x03, 0xFF, 0x11, 0x13, b'o', b'k'];
+        for kind in KINDS {
+            let new_codec = || {
+                let sonar = SonarCodec::new(Box::new(FSK::default()), SonarCodecConfig::default());
+                FramedCodec::new(sonar, ByteFraming::new(kind).unwrap()).with_max_frame_len(8)
+            };
+            let tx = new_codec();
+            let mut rx = new_codec();
+
+            let mut signal = vec![0.0; 1_013];
+            signal.extend(tx.encode(&payload).unwrap());
+            signal.extend(vec![0.0; 4_000]);
+
+            let frames: Vec<Vec<u8>> = signal.chunks(1_024).flat_map(|c| rx.decode_frames(c).unwrap()).collect();
+            assert_eq!(frames, vec![payload[..8].to_vec(), payload[8..].to_vec()], "{kind:?}");
+        }
+    }
+}
@@ -0,0 +1,441 @@
+// * HDLC bit-oriented framing: flags, bit stuffing, FCS-16, NRZI line coding and a synchronous codec over any modem
+
+use std::error::Error;
+
+use crc::{CRC_16_IBM_SDLC, Crc};
+
+use super::{CodecTrait, FrameKind};
+use crate::modem::ModemTrait;
+
+/// The only flag HDLC bit stuffing can keep unique: `0b0111_1110`.
+pub const HDLC_FLAG: u8 = 0x7E;
+
+/// Frame check sequence used by HDLC, X.25 and AX.25 (CRC-16/IBM-SDLC).
+const FCS: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
+
+/// Computes the HDLC frame check sequence of `data`.
+pub fn fcs(data: &[u8]) -> u16 {
+    FCS.checksum(data)
+}
+
+/// NRZI-encodes a bitstream, starting from `level`.
+///
+/// A 0 toggles the line level and a 1 keeps it, as in AX.25. The returned levels are
+/// the bits to hand to the modem (`true` being the mark tone).
+pub fn nrzi_encode(bits: &[bool], mut level: bool) -> Vec<bool> {
+    bits.iter()
+        .map(|&bit| {
+            level ^= !bit;
+            level
+        })
+        .collect()
+}
+
+/// Decodes NRZI line levels back into bits, given the level before the first one.
+pub fn nrzi_decode(levels: &[bool], mut previous: bool) -> Vec<bool> {
+    levels
+        .iter()
+        .map(|&level| {
+            let bit = level == previous;
+            previous = level;
+            bit
+        })
+        .collect()
+}
+
+/// What a synchronous line carries while no frame is being sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleFill {
+    /// Back-to-back flags, so the receiver stays in sync (interframe time fill).
+    Flags,
+    /// A steady run of 1s (mark idle). Fifteen or more of them tell the receiver the link is idle.
+    Marks,
+}
+
+/// HDLC framer: wraps frames in flags and stuffs their bits so the flag never shows up inside.
+///
+/// Bytes go out LSB first, followed by their FCS (low byte first). After every five
+/// consecutive 1s a 0 is inserted, so six 1s in a row only ever appear in a flag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hdlc {
+    opening_flags: usize, // Flags sent before every frame, to let the receiver settle
+    closing_flags: usize, // Flags sent after every frame
+    idle_fill: IdleFill,  // What pads the line between and after frames
+}
+
+impl Default for Hdlc {
+    fn default() -> Self {
+        Self { opening_flags: 16, closing_flags: 2, idle_fill: IdleFill::Flags }
+    }
+}
+
+impl Hdlc {
+    /// Creates a framer for the given frame kind.
+    ///
+    /// Only [`FrameKind::BitOriented`] with the `0x7E` flag can be bit stuffed.
+    pub fn new(kind: FrameKind) -> Result<Self, Box<dyn Error>> {
+        match kind {
+            FrameKind::BitOriented { flag: HDLC_FLAG } => Ok(Self::default()),
+            FrameKind::BitOriented { flag } => {
+                Err(format!("HDLC bit stuffing needs the 0x7E flag, got 0x{flag:02X}").into())
+            }
+            other => Err(format!("{other:?} is not a bit-oriented frame kind").into()),
+        }
+    }
+
+    /// Sets how many flags precede every frame (at least one).
+    pub fn with_opening_flags(mut self, flags: usize) -> Self {
+        self.opening_flags = flags.max(1);
+        self
+    }
+
+    /// Sets what the line carries between frames.
+    pub fn with_idle_fill(mut self, idle_fill: IdleFill) -> Self {
+        self.idle_fill = idle_fill;
+        self
+    }
+
+    /// Frames `data`: opening flags, the stuffed frame and its FCS, then closing flags.
+    pub fn encode(&self, data: &[u8]) -> Vec<bool> {
+        self.encode_frames([data])
+    }
+
+    /// Frames several frames in a row: opening flags, every stuffed frame followed by a single
+    /// flag (closing it and opening the next), then closing flags.
+    pub fn encode_frames<'a>(&self, frames: impl IntoIterator<Item = &'a [u8]>) -> Vec<bool> {
+        let mut bits = Self::flags(self.opening_flags);
+        for data in frames {
+            let fcs = fcs(data);
+            let mut ones = 0;
+            for byte in data.iter().copied().chain(fcs.to_le_bytes()) {
+                for i in 0..8 {
+                    let bit = (byte >> i) & 1 == 1;
+                    bits.push(bit);
+                    ones = if bit { ones + 1 } else { 0 };
+                    if ones == 5 {
+                        bits.push(false);
+                        ones = 0;
+                    }
+                }
+            }
+            bits.extend(Self::flags(1));
+        }
+        bits.extend(Self::flags(self.closing_flags.saturating_sub(1)));
+        bits
+    }
+
+    /// Abort sequence: eight 1s, which no frame or flag contains, so the receiver drops the
+    /// frame in progress. Whatever follows it should open with a flag.
+    pub fn abort(&self) -> Vec<bool> {
+        vec![true; 8]
+    }
+
+    /// `len` bits of idle fill.
+    pub fn idle(&self, len: usize) -> Vec<bool> {
+        match self.idle_fill {
+            IdleFill::Flags => (0..len).map(|i| (HDLC_FLAG >> (i % 8)) & 1 == 1).collect(),
+            IdleFill::Marks => vec![true; len],
+        }
+    }
+
+    /// `count` flags, LSB first.
+    fn flags(count: usize) -> Vec<bool> {
+        (0..count * 8).map(|i| (HDLC_FLAG >> (i % 8)) & 1 == 1).collect()
+    }
+
+    /// Creates a receiver for frames sent by this framer.
+    pub fn deframer(&self) -> HdlcDeframer {
+        HdlcDeframer::default()
+    }
+}
+
+/// Bit-by-bit HDLC receiver.
+///
+/// Removes stuffed bits, splits the stream on flags and only releases frames whose FCS
+/// checks out. Seven or more 1s in a row are neither data nor a flag, so the frame in
+/// progress is dropped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HdlcDeframer {
+    bits: Vec<bool>, // Unstuffed bits since the last flag
+    ones: usize,     // Consecutive 1s received
+    in_frame: bool,  // Whether a flag has been seen since the last invalid run of 1s
+}
+
+impl HdlcDeframer {
+    /// Shortest frame worth checking: one byte and the FCS. Codecs splitting a payload may
+    /// leave a single byte for the last frame.
+    const MIN_FRAME_LEN: usize = 3;
+    /// Longest frame kept before giving up on a missing closing flag.
+    const MAX_FRAME_LEN: usize = 1024;
+
+    /// Feeds one received bit, returning a frame (without its FCS) when one completes.
+    pub fn push(&mut self, bit: bool) -> Option<Vec<u8>> {
+        if bit {
+            self.ones += 1;
+            if self.ones > 6 {
+                self.bits.clear();
+                self.in_frame = false;
+            } else {
+                self.bits.push(true);
+            }
+            return None;
+        }
+
+        let ones = std::mem::replace(&mut self.ones, 0);
+        match ones {
+            5 => None, // Stuffed bit
+            6 => {
+                // Flag: drop its first seven bits, which were taken as data
+                self.bits.truncate(self.bits.len().saturating_sub(7));
+                let frame = self.in_frame.then(|| self.take_frame()).flatten();
+                self.bits.clear();
+                self.in_frame = true;
+                frame
+            }
+            _ => {
+                self.bits.push(false);
+                if self.bits.len() > Self::MAX_FRAME_LEN * 8 {
+                    self.bits.clear();
+                    self.in_frame = false;
+                }
+                None
+            }
+        }
+    }
+
+    /// Packs the collected bits into bytes and checks the FCS.
+    fn take_frame(&self) -> Option<Vec<u8>> {
+        if !self.bits.len().is_multiple_of(8) || self.bits.len() < Self::MIN_FRAME_LEN * 8 {
+            return None;
+        }
+        let mut bytes: Vec<u8> = self
+            .bits
+            .chunks_exact(8)
+            .map(|byte| byte.iter().rev().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+            .collect();
+
+        let received = u16::from_le_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]);
+        bytes.truncate(bytes.len() - 2);
+        (fcs(&bytes) == received).then_some(bytes)
+    }
+}
+
+/// One symbol clock of the receiver, sampling the audio at its own phase.
+#[derive(Debug, Clone)]
+struct SymbolSlicer {
+    next: f64,   // Absolute sample position of the next symbol
+    level: bool, // Last line level, for NRZI decoding
+    deframer: HdlcDeframer,
+}
+
+/// Synchronous HDLC link over any modem: frames go straight onto the modem's bitstream,
+/// with no per-byte start and stop bits.
+///
+/// A byte then costs 8 bits plus the odd stuffed 0 instead of the 10 of an 8N1 character,
+/// about 20% more throughput at the same baud rate. Payloads longer than `max_frame_len`
+/// are split over several back-to-back frames, and every transmission is padded with idle
+/// fill to a whole number of symbols.
+///
+/// The receiver runs several symbol clocks spread over one symbol period and the FCS decides
+/// which of them got each frame right; [`Ax25Codec`](super::ax25::Ax25Codec) is built on it.
+/// Every symbol is primed with the one before it, which suits modems deciding
+/// symbols on their own (tones) or against the previous one (DBPSK); coherent phase modems
+/// want the all-mark reference only [`SonarCodec`](super::SonarCodec) gives them. Adaptive
+/// modems learn from every symbol the slicers decide.
+pub struct HdlcCodec {
+    modem: Box<dyn ModemTrait>,
+    hdlc: Hdlc,
+    nrzi: bool, // Whether bits are NRZI coded on the line
+    max_frame_len: usize,
+    samples_per_symbol: f64,
+    slicers: Vec<SymbolSlicer>,
+    audio_buffer: Vec<f32>,
+    buffer_start: usize,                  // Absolute sample position of `audio_buffer[0]`
+    recent_frames: Vec<(usize, Vec<u8>)>, // Frames already returned, to drop the other slicers' copies
+}
+
+impl HdlcCodec {
+    /// Symbol clocks spread over one symbol period.
+    const SLICERS: usize = 8;
+    /// Bits within which two slicers' copies of a frame count as the same frame. The
+    /// shortest frame plus its flag is 32 bits, so real repeats are never dropped.
+    const DUPLICATE_WINDOW_BITS: usize = 16;
+
+    /// Creates a codec sending `baud_rate` symbols per second, framed by `hdlc`.
+    pub fn new(modem: Box<dyn ModemTrait>, sample_rate: u32, baud_rate: f32, hdlc: Hdlc) -> Self {
+        let samples_per_symbol = sample_rate as f64 / baud_rate as f64;
+        Self {
+            modem,
+            hdlc,
+            nrzi: false,
+            max_frame_len: 256,
+            samples_per_symbol,
+            slicers: (0..Self::SLICERS)
+                .map(|i| SymbolSlicer {
+                    next: samples_per_symbol * i as f64 / Self::SLICERS as f64,
+                    level: true,
+                    deframer: hdlc.deframer(),
+                })
+                .collect(),
+            audio_buffer: Vec::new(),
+            buffer_start: 0,
+            recent_frames: Vec::new(),
+        }
+    }
+
+    /// NRZI codes the line (a 0 toggles the level), as AX.25 does. The receiver then does
+    /// not care which way round the modem's mark and space are.
+    pub fn with_nrzi(mut self, nrzi: bool) -> Self {
+        self.nrzi = nrzi;
+        self
+    }
+
+    /// Sets the longest frame `encode` sends (1 to 1022 bytes, leaving room for the FCS);
+    /// longer payloads are split.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len.clamp(1, HdlcDeframer::MAX_FRAME_LEN - 2);
+        self
+    }
+
+    /// Modulates a bitstream, NRZI coded if enabled and padded with idle fill to whole symbols.
+    pub fn encode_bits(&self, bits: &[bool]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut bits = bits.to_vec();
+        let bits_per_symbol = self.modem.bits_per_symbol();
+        bits.extend(self.hdlc.idle(bits.len().next_multiple_of(bits_per_symbol) - bits.len()));
+        if self.nrzi {
+            bits = nrzi_encode(&bits, true);
+        }
+        self.modem.modulate(&bits)
+    }
+
+    /// Modulates `data` as a single frame, whatever its length.
+    pub fn encode_frame(&self, data: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.encode_bits(&self.hdlc.encode(data))
+    }
+
+    /// Feeds audio to the receiver, returning every new frame with a valid FCS (FCS removed).
+    pub fn decode_frames(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        self.audio_buffer.extend_from_slice(samples);
+        let buffer_end = self.buffer_start + self.audio_buffer.len();
+        let symbol_len = self.samples_per_symbol.round() as usize;
+
+        let mut found = Vec::new();
+        for slicer in &mut self.slicers {
+            while slicer.next.round() as usize + symbol_len <= buffer_end {
+                let start = slicer.next.round() as usize - self.buffer_start;
+                self.modem.prime(&self.audio_buffer[start.saturating_sub(symbol_len)..start]);
+                let energies = self.modem.analyze_symbol(&self.audio_buffer[start..start + symbol_len])?;
+                let levels: Vec<bool> = energies.iter().map(|&(mark_energy, space_energy)| mark_energy > space_energy).collect();
+                self.modem.learn(&energies, &levels);
+                for level in levels {
+                    let bit = if self.nrzi { level == slicer.level } else { level };
+                    if let Some(bytes) = slicer.deframer.push(bit) {
+                        found.push((slicer.next as usize, bytes));
+                    }
+                    slicer.level = level;
+                }
+                slicer.next += self.samples_per_symbol;
+            }
+        }
+
+        // Every slicer close enough to the right phase decodes the same frame
+        found.sort_by_key(|(pos, _)| *pos);
+        let symbols = (Self::DUPLICATE_WINDOW_BITS as f64 / self.modem.bits_per_symbol() as f64).max(2.0);
+        let window = (self.samples_per_symbol * symbols) as usize;
+        let mut frames = Vec::new();
+        for (pos, bytes) in found {
+            if self.recent_frames.iter().any(|(p, b)| *b == bytes && pos.abs_diff(*p) < window) {
+                continue;
+            }
+            self.recent_frames.push((pos, bytes.clone()));
+            frames.push(bytes);
+        }
+        self.recent_frames.retain(|(p, _)| p + (self.samples_per_symbol * 64.0) as usize > buffer_end);
+
+        // Keep the audio from the symbol before the slowest slicer onwards
+        let keep_from = self.slicers.iter().map(|s| s.next as usize).min().unwrap_or(buffer_end).saturating_sub(symbol_len);
+        self.audio_buffer.drain(..keep_from.saturating_sub(self.buffer_start));
+        self.buffer_start = self.buffer_start.max(keep_from);
+        Ok(frames)
+    }
+}
+
+impl CodecTrait for HdlcCodec {
+    fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.encode_bits(&self.hdlc.encode_frames(payload.chunks(self.max_frame_len)))
+    }
+
+    fn decode(&mut self, samples: &[f32]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let payload: Vec<u8> = self.decode_frames(samples)?.concat();
+        Ok(if payload.is_empty() { None } else { Some(payload) })
+    }
+
+    /// Drops every slicer's partial frame and NRZI level, so the next frame has to open
+    /// with a flag, and clears the duplicate filter.
+    fn reset_state(&mut self) {
+        for slicer in &mut self.slicers {
+            slicer.deframer = self.hdlc.deframer();
+            slicer.level = true;
+        }
+        self.recent_frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hdlc_roundtrip_through_nrzi() {
+        // Plenty of 1s, so stuffing kicks in; 0x7E in the payload must not end the frame
+        let data = [0xFF, 0x7E, 0x3F, 0x00, 0xFC, 0x1F, 0x7E];
+        let hdlc = Hdlc::default();
+        let line = nrzi_encode(&hdlc.encode(&data), true);
+
+        let mut deframer = hdlc.deframer();
+        let frames: Vec<Vec<u8>> = nrzi_decode(&line, true)
+            .into_iter()
+            .filter_map(|bit| deframer.push(bit))
+            .collect();
+        assert_eq!(frames, vec![data.to_vec()]);
+    }
+
+    #[test]
+    fn aborted_frames_are_dropped_and_idle_fill_is_skipped() {
+        assert!(Hdlc::new(FrameKind::BitOriented { flag: 0x7F }).is_err());
+        let hdlc = Hdlc::new(FrameKind::BitOriented { flag: HDLC_FLAG }).unwrap().with_idle_fill(IdleFill::Marks);
+        let mut bits = hdlc.encode(b"never finished");
+        bits.truncate(bits.len() - 40);
+        bits.extend(hdlc.abort());
+        bits.extend(hdlc.idle(30));
+        bits.extend(hdlc.encode_frames([&b"first"[..], b"second"]));
+
+        let mut deframer = hdlc.deframer();
+        let frames: Vec<Vec<u8>> = bits.into_iter().filter_map(|bit| deframer.push(bit)).collect();
+        assert_eq!(frames, vec![b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    fn hdlc_codec_splits_payloads_over_a_multi_bit_modem() {
+        use crate::modem::MFSK;
+
+        let new_codec = || {
+            let modem = Box::new(MFSK::new(48_000, 1_000.0, 300.0, 16, 160));
+            HdlcCodec::new(modem, 48_000, 300.0, Hdlc::default()).with_max_frame_len(16).with_nrzi(true)
+        };
+        let tx = new_codec();
+        let mut rx = new_codec();
+
+        // Three frames of 16 bytes and one of a single byte
+        let payload: Vec<u8> = (0..49u8).map(|i| i.wrapping_mul(37) ^ 0x7E).collect();
+        let mut signal = vec![0.0; 1_013];
+        signal.extend(tx.encode(&payload).unwrap());
+        signal.extend(vec![0.0; 2_000]);
+
+        let mut received = Vec::new();
+        for chunk in signal.chunks(1_000) {
+            received.extend(rx.decode(chunk).unwrap().unwrap_or_default());
+        }
+        assert_eq!(received, payload);
+    }
+}
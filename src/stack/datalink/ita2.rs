@@ -0,0 +1,107 @@
+// * ITA2 (Baudot-Murray) 5-bit character set, as used by RTTY
+
+/// Switches the receiver to the letters table.
+pub const LTRS: u8 = 0x1F;
+/// Switches the receiver to the figures table.
+pub const FIGS: u8 = 0x1B;
+
+/// Letters table, indexed by code. Shift codes are `\0` placeholders.
+const LETTERS: [char; 32] = [
+    '\0', 'E', '\n', 'A', ' ', 'S', 'I', 'U', '\r', 'D', 'R', 'J', 'N', 'F', 'C', 'K',
+    'T', 'Z', 'L', 'W', 'H', 'Y', 'P', 'Q', 'O', 'B', 'G', '\0', 'M', 'X', 'V', '\0',
+];
+
+/// Figures table, indexed by code. `!`, `#` and `&` fill the national-use positions the
+/// way most amateur RTTY software does; 0x09 is WRU (ENQ) and 0x0B the bell.
+const FIGURES: [char; 32] = [
+    '\0', '3', '\n', '-', ' ', '\'', '8', '7', '\r', '\x05', '4', '\x07', ',', '!', ':', '(',
+    '5', '+', ')', '2', '#', '6', '0', '1', '9', '?', '&', '\0', '.', '/', '=', '\0',
+];
+
+/// ITA2 encoder/decoder, keeping track of the LTRS/FIGS shift state.
+///
+/// The codes are the 5-bit characters `SonarCodec` sends with `data_bits: 5`. With
+/// unshift-on-space (on by default, like most RTTY software) a space also returns the
+/// receiver to letters, so the encoder repeats FIGS after spaces in figure runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ita2 {
+    figures: Option<bool>, // Current shift, `None` until the first shift code
+    unshift_on_space: bool,
+}
+
+impl Default for Ita2 {
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl Ita2 {
+    pub fn new(unshift_on_space: bool) -> Self {
+        Self { figures: None, unshift_on_space }
+    }
+
+    /// Encodes text into ITA2 codes, inserting LTRS/FIGS wherever the shift changes.
+    ///
+    /// Lowercase letters are sent as uppercase; characters ITA2 cannot represent are dropped.
+    pub fn encode(&mut self, text: &str) -> Vec<u8> {
+        let mut codes = Vec::with_capacity(text.len() + 1);
+        for c in text.chars().map(|c| c.to_ascii_uppercase()) {
+            let letter = LETTERS.iter().position(|&l| l == c && c != '\0');
+            let figure = FIGURES.iter().position(|&f| f == c && c != '\0');
+            let (code, figures) = match (letter, figure) {
+                // In both tables (space, CR, LF): no shift needed
+                (Some(code), Some(_)) => (code as u8, self.figures),
+                (Some(code), None) => (code as u8, Some(false)),
+                (None, Some(code)) => (code as u8, Some(true)),
+                (None, None) => continue,
+            };
+            if figures != self.figures || self.figures.is_none() {
+                let figures = figures.unwrap_or(false);
+                codes.push(if figures { FIGS } else { LTRS });
+                self.figures = Some(figures);
+            }
+            codes.push(code);
+            if c == ' ' && self.unshift_on_space {
+                self.figures = Some(false);
+            }
+        }
+        codes
+    }
+
+    /// Decodes ITA2 codes into text, following the shift codes. Only the low 5 bits are used.
+    pub fn decode(&mut self, codes: &[u8]) -> String {
+        let mut text = String::with_capacity(codes.len());
+        for &code in codes {
+            match code & 0x1F {
+                LTRS => self.figures = Some(false),
+                FIGS => self.figures = Some(true),
+                0 => {}
+                code => {
+                    let c = if self.figures == Some(true) { FIGURES[code as usize] } else { LETTERS[code as usize] };
+                    text.push(c);
+                    if c == ' ' && self.unshift_on_space {
+                        self.figures = Some(false);
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ita2_shifts_between_letters_and_figures() {
+        let codes = Ita2::default().encode("cq de n0call 73 73, k\r\n");
+        assert_eq!(codes[0], LTRS);
+        assert_eq!(Ita2::default().decode(&codes), "CQ DE N0CALL 73 73, K\r\n");
+
+        // Without unshift-on-space, figures survive the space
+        let codes = Ita2::new(false).encode("599 599");
+        assert_eq!(codes.iter().filter(|&&c| c == FIGS).count(), 1);
+        assert_eq!(Ita2::new(false).decode(&codes), "599 599");
+    }
+}
@@ -1,11 +1,55 @@
 // C:\...\sonar\src\stack\datalink\mod.rs
 
-use crate::modem::ModemTrait;
+use crate::modem::{BandProfile, Equalizer, EqualizerConfig, ModemTrait, StreamModulator, ToneBank, apply_fade, bit_llrs};
 use dev_utils::{debug, info, trace, warn};
 use std::error::Error;
 
-const BITS_PER_CHARACTER: usize = 10;
+pub mod frame;
+pub use frame::FrameKind;
+
+pub mod hdlc;
+pub use hdlc::{Hdlc, HdlcCodec, HdlcDeframer, IdleFill};
+
+pub mod ax25;
+pub use ax25::{Ax25Address, Ax25Codec, Ax25Frame};
+
+pub mod ita2;
+pub use ita2::Ita2;
+
+pub mod sync;
+pub use sync::SyncWord;
+
+pub mod uart;
+pub use uart::{BitOrder, Parity, StopBits, UartFraming};
+
+pub mod ppp;
+pub use ppp::{AsyncPpp, AsyncPppDeframer, PppCodec};
+
+pub mod bisync;
+pub use bisync::{Bisync, BisyncDeframer};
+
+pub mod ddcmp;
+pub use ddcmp::{Ddcmp, DdcmpDeframer};
+
+pub mod framed;
+pub use framed::{ByteDeframer, ByteFraming, FramedCodec};
+
 const LEADER_TONE_CHARS: usize = 5;
+/// Share of the early-late timing error corrected on every frame.
+const TIMING_GAIN: f32 = 0.5;
+/// Share of every timing correction taken as clock drift, and kept for the following frames.
+const DRIFT_GAIN: f32 = 0.02;
+/// Largest clock drift the timing loop believes in (1000 ppm); sound cards stay well within it.
+const MAX_DRIFT: f32 = 1e-3;
+/// Share of the locked signal's level a frame needs to count as part of it. Once a
+/// transmission ends only noise is left, far below; its chance patterns score no frames.
+const SQUELCH: f32 = 0.25;
+/// Share of every decided frame's level taken into the signal level.
+const LEVEL_GAIN: f32 = 0.1;
+/// Normalized correlation the sync word needs with the bits received, out of 1.
+const MIN_SYNC_CORRELATION: f32 = 0.8;
+/// Chips of the sync word that may come out wrong, however unsure of them the receiver was.
+const MAX_SYNC_ERRORS: usize = 2;
 
 pub trait CodecTrait {
     fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>>;
@@ -17,178 +61,1093 @@ pub struct SonarCodec {
     modem: Box<dyn ModemTrait>,
     config: SonarCodecConfig,
     audio_buffer: Vec<f32>,
+    buffer_start: usize,        // Stream position of the first buffered sample
+    equalizer: Option<Equalizer>,
+    equalized_buffer: Vec<f32>, // `audio_buffer` through the equalizer, when there is one
+    received: Vec<u8>,          // Bytes decided since the signal was detected
+    reference: Option<Box<dyn StreamModulator + Send>>, // Rebuilds the signal frame by frame, for the equalizer
+    reference_len: usize,       // Samples of the signal rebuilt so far
+    signal_start: usize,        // Stream position where the transmission being received started
+    equalizing: bool,           // Whether decided frames line up with the equalizer's reference
+    tone_bank: Option<ToneBank>, // Symbol-long tone energies of `signal()`, for modems keyed by tones
+    frame_phase: f32,           // Sub-sample part of where the timing loop expects the next frame
+    clock_drift: f32,           // Extra received samples per sent sample, as estimated by the timing loop
+    missed: usize,              // Samples searched since the last frame found, while receiving
+    signal_level: f32,          // Mean energy of the decided bits of the signal being received
+    stats: ReceiveStats,
     is_receiving: bool,
 }
 
+/// What the receiver measured about the signal it is locked on, or last was.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReceiveStats {
+    /// Frames decided since the signal was detected.
+    pub frames: usize,
+    /// Frames the timing loop lost and had to search for again.
+    pub resyncs: usize,
+    /// How much faster the receiver's sample clock runs than the transmitter's, in parts
+    /// per million. Estimated from the timing loop's corrections, so it takes a couple
+    /// hundred frames to settle. Every transmission starts from 0, as the next one may come
+    /// from another sound card; without a sync word the receiver cannot tell where one ends,
+    /// though, and only starts over on `reset_state`.
+    pub clock_offset_ppm: f32,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct SonarCodecConfig {
     pub sample_rate: u32,
     pub baud_rate: u32,
+    /// Symbol rate to use instead of `baud_rate` when it is not a whole number, like RTTY's 45.45 baud.
+    pub exact_baud_rate: Option<f32>,
+    /// Signal-to-noise ratio a frame needs: the summed energy its bits were decided on over
+    /// the summed energy of the losing sides, penalized when the marks (or spaces) vary in strength.
     pub confidence_threshold: f32,
+    /// Mean |LLR| the bits of a frame need on top, see [`bit_llrs`]: at the default of 4 every
+    /// bit is about 98% sure. Frames clearing both thresholds are ranked by it.
+    pub min_mean_llr: f32,
+    pub data_bits: usize, // Data bits per character (5 to 8)
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub bit_order: BitOrder, // Order the data bits go out in
+    pub fade_samples: usize, // Fade-in/out length of every transmission, so speakers don't click
+    pub equalizer: Option<EqualizerConfig>, // Echo canceller for reverberant rooms, off by default
+    pub preamble_chars: usize, // All-mark characters (symbols, for symbol frames) opening every transmission
+    /// Sent after the preamble, so the receiver locks on it instead of on the first frame that
+    /// scores. Off by default, as receivers that don't expect one would take it for data. Both
+    /// ends need the same word; without one, noise ahead of a transmission can pass for frames.
+    pub sync_word: Option<SyncWord>,
+}
+
+impl Default for SonarCodecConfig {
+    /// 300 baud 8N1, matching `FSK::default()`.
+    fn default() -> Self {
+        Self {
+            sample_rate: crate::modem::SAMPLE_RATE,
+            baud_rate: 300,
+            exact_baud_rate: None,
+            confidence_threshold: 4.0,
+            min_mean_llr: 4.0,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            bit_order: BitOrder::LsbFirst,
+            fade_samples: 0,
+            equalizer: None,
+            preamble_chars: LEADER_TONE_CHARS,
+            sync_word: None,
+        }
+    }
+}
+
+impl SonarCodecConfig {
+    /// 8N1 framing for a [`BandProfile`], with 5 ms fades.
+    ///
+    /// Pair it with the modem built by the same profile, e.g. `BandProfile::fsk`.
+    pub fn for_band(profile: BandProfile, sample_rate: u32, baud_rate: u32) -> Result<Self, Box<dyn Error>> {
+        profile.check(sample_rate)?;
+        Ok(Self {
+            sample_rate,
+            baud_rate,
+            fade_samples: sample_rate as usize / 200,
+            ..Self::default()
+        })
+    }
+
+    /// Amateur RTTY framing: 45.45 baud, 5 data bits (ITA2 codes) and 1.5 stop bits.
+    /// RTTY has no sync word, so other stations can copy it.
+    ///
+    /// Pair it with [`FSK::rtty`](crate::modem::FSK::rtty) and translate the codes with [`Ita2`].
+    pub fn rtty(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            baud_rate: 45,
+            exact_baud_rate: Some(45.45),
+            data_bits: 5,
+            stop_bits: StopBits::OneAndHalf,
+            sync_word: None,
+            ..Self::default()
+        }
+    }
+
+    /// Symbols per second: `exact_baud_rate` if set, `baud_rate` otherwise.
+    pub fn symbol_rate(&self) -> f32 {
+        self.exact_baud_rate.unwrap_or(self.baud_rate as f32)
+    }
+
+    /// The character framing these settings describe.
+    pub fn framing(&self) -> UartFraming {
+        UartFraming { data_bits: self.data_bits, parity: self.parity, stop_bits: self.stop_bits, bit_order: self.bit_order }
+    }
 }
 
 impl SonarCodec {
+    /// # Panics
+    /// If `config.data_bits` is not between 5 and 8.
     pub fn new(modem: Box<dyn ModemTrait>, config: SonarCodecConfig) -> Self {
+        assert!(
+            (5..=8).contains(&config.data_bits),
+            "SonarCodec supports 5 to 8 data bits, got {}",
+            config.data_bits
+        );
+        let samples_per_symbol = (config.sample_rate as f32 / config.symbol_rate()).round() as usize;
+        let tone_bank = modem.tone_frequencies().map(|tones| ToneBank::new(&tones, config.sample_rate, samples_per_symbol.max(1)));
         Self {
             modem,
             config,
             audio_buffer: Vec::with_capacity((config.sample_rate * 2) as usize),
+            buffer_start: 0,
+            equalizer: config.equalizer.map(Equalizer::new),
+            equalized_buffer: Vec::new(),
+            received: Vec::new(),
+            reference: None,
+            reference_len: 0,
+            signal_start: 0,
+            equalizing: false,
+            tone_bank,
+            frame_phase: 0.0,
+            clock_drift: 0.0,
+            missed: 0,
+            signal_level: 0.0,
+            stats: ReceiveStats::default(),
             is_receiving: false,
         }
     }
 
-    fn samples_per_bit(&self) -> f32 {
-        self.config.sample_rate as f32 / self.config.baud_rate as f32
+    /// Measurements of the signal being received, or of the last one, see [`ReceiveStats`].
+    pub fn stats(&self) -> ReceiveStats {
+        match self.is_receiving {
+            true => ReceiveStats { clock_offset_ppm: self.clock_drift * 1e6, ..self.stats },
+            false => self.stats,
+        }
+    }
+
+    /// Samples per modem symbol; `baud_rate` counts symbols, not bits.
+    fn samples_per_symbol(&self) -> f32 {
+        self.config.sample_rate as f32 / self.config.symbol_rate()
+    }
+
+    /// Samples per symbol as they arrive: stretched or shrunk by the clock drift.
+    fn received_samples_per_symbol(&self) -> f32 {
+        self.samples_per_symbol() * (1.0 + self.clock_drift)
+    }
+
+    /// Bits every character is checked on: the start bit, the data bits, the parity bit and one stop bit.
+    fn bits_per_character(&self) -> usize {
+        self.config.framing().bits_per_character()
+    }
+
+    /// Whole symbols sent for one character.
+    ///
+    /// The stop bits are stretched with extra marks until the character ends in a whole
+    /// all-mark symbol, so the symbol before every character is a known phase reference.
+    /// For binary modems that is just the plain stop bits (1.5 being sent as 2, ended halfway).
+    fn symbols_per_character(&self) -> usize {
+        let bits_per_symbol = self.modem.bits_per_symbol();
+        let stop_bits = self.config.stop_bits.half_bits().div_ceil(2);
+        (self.bits_per_character() - 1 + stop_bits.max(bits_per_symbol)).div_ceil(bits_per_symbol)
+    }
+
+    /// Whether the last stop bit is only half as long (1.5 stop bits on a binary modem that can
+    /// end a symbol early).
+    fn has_half_stop_bit(&self) -> bool {
+        self.config.stop_bits == StopBits::OneAndHalf && self.modem.bits_per_symbol() == 1 && self.modem.streamer().is_some()
+    }
+
+    /// Distance between the starts of consecutive characters.
+    fn samples_per_character(&self) -> f32 {
+        let half = if self.has_half_stop_bit() { 0.5 } else { 0.0 };
+        self.samples_per_symbol() * (self.symbols_per_character() as f32 - half)
+    }
+
+    /// Exact length of one frame: a UART character, or a single symbol for symbol-framed modems.
+    fn frame_length(&self) -> f32 {
+        match self.modem.frames_symbols() {
+            true => self.samples_per_symbol(),
+            false => self.samples_per_character(),
+        }
+    }
+
+    /// Symbols a frame is decided from: the symbol of a symbol frame, or those covering the
+    /// checked bits of a character.
+    fn decided_symbols(&self) -> usize {
+        match self.modem.frames_symbols() {
+            true => 1,
+            false => self.bits_per_character().div_ceil(self.modem.bits_per_symbol()),
+        }
+    }
+
+    /// Samples in one frame, rounded.
+    fn samples_per_frame(&self) -> usize {
+        self.frame_length().round() as usize
     }
 
-    fn samples_per_character(&self) -> usize {
-        (self.samples_per_bit() * BITS_PER_CHARACTER as f32).round() as usize
+    /// Audio kept in front of every frame: one symbol, always all marks (preamble, end of the
+    /// sync word or previous stop bits), which modems may use as their phase reference.
+    fn lookback(&self) -> usize {
+        self.samples_per_symbol().round() as usize
     }
 
-    fn analyze_character_frame(&self, frame_samples: &[f32]) -> (f32, u8) {
-        let samples_per_bit = self.samples_per_bit();
-        let mut bits = [false; BITS_PER_CHARACTER];
-        let mut signals = [0.0; BITS_PER_CHARACTER];
-        let mut noises = [0.0; BITS_PER_CHARACTER];
+    /// Distance of the early and late timing probes from the expected frame start.
+    fn timing_gate(&self) -> usize {
+        (self.samples_per_symbol() * 0.25).round() as usize
+    }
+
+    /// Payload bytes carried by one symbol frame, after its leading length byte.
+    fn bytes_per_symbol_frame(&self) -> usize {
+        (self.modem.bits_per_symbol() / 8).min(256).saturating_sub(1)
+    }
+
+    /// Runs the modem over `symbols` consecutive symbols of `signal()` from `pos` on,
+    /// returning `(mark_energy, space_energy)` for every bit.
+    ///
+    /// Modems keyed by tones read their energies off the tone bank instead of analyzing the
+    /// audio again: the search tries every offset, and the bank already measured them all.
+    /// The bank's windows are a whole symbol long, though; symbols a sample longer or shorter,
+    /// from a fractional symbol length or the clock drift, are analyzed from the audio.
+    fn analyze_bits(&self, pos: usize, symbols: usize) -> Option<Vec<(f32, f32)>> {
+        let signal = self.signal();
+        let samples_per_symbol = self.received_samples_per_symbol();
+        let mut energies = Vec::with_capacity(symbols * self.modem.bits_per_symbol());
 
         let mut current_pos_f32: f32 = 0.0;
-        for i in 0..BITS_PER_CHARACTER {
-            let start = current_pos_f32.round() as usize;
-            let end = (current_pos_f32 + samples_per_bit).round() as usize;
-            current_pos_f32 += samples_per_bit;
-
-            if end > frame_samples.len() { return (0.0, 0); }
-
-            if let Ok((mark_energy, space_energy)) = self.modem.analyze_bit(&frame_samples[start..end]) {
-                if mark_energy > space_energy {
-                    bits[i] = true;
-                    signals[i] = mark_energy;
-                    noises[i] = space_energy;
-                } else {
-                    bits[i] = false;
-                    signals[i] = space_energy;
-                    noises[i] = mark_energy;
-                }
-            } else {
-                return (0.0, 0);
-            }
+        for _ in 0..symbols {
+            let start = pos + current_pos_f32.round() as usize;
+            let end = pos + (current_pos_f32 + samples_per_symbol).round() as usize;
+            current_pos_f32 += samples_per_symbol;
+
+            if end > signal.len() { return None; }
+
+            let bank = self.tone_bank.as_ref().filter(|bank| bank.window() == end - start);
+            let symbol_energies = match bank.and_then(|bank| bank.energies(start)) {
+                Some(tone_energies) => self.modem.analyze_tones(tone_energies),
+                None => self.modem.analyze_symbol(&signal[start..end]),
+            };
+            energies.extend(symbol_energies.ok()?);
         }
+        Some(energies)
+    }
 
-        if bits[0] || !bits[BITS_PER_CHARACTER - 1] { return (0.0, 0); }
+    /// Decides every bit of a frame and scores it: the mean reliability (|LLR|) of its bits,
+    /// penalized when the strength of the marks (or spaces) varies from bit to bit.
+    ///
+    /// The noise behind the LLRs is estimated over the whole frame. A score of 4 means the
+    /// bits are about 98% sure on average; the penalty weeds out frames straddling two real ones.
+    /// Frames whose signal-to-noise ratio, penalized the same way, stays within the
+    /// `confidence_threshold` score 0, and so do frames below [`SQUELCH`] of the signal's level
+    /// while receiving.
+    fn frame_confidence(&self, energies: &[(f32, f32)]) -> (f32, Vec<bool>) {
+        let llrs = bit_llrs(energies);
+        let bits: Vec<bool> = llrs.iter().map(|&llr| llr > 0.0).collect();
+        let signals: Vec<f32> = energies.iter().map(|&(mark, space)| mark.max(space)).collect();
+        let level = signals.iter().sum::<f32>() / signals.len() as f32;
+        if self.is_receiving && level < SQUELCH * self.signal_level {
+            return (0.0, bits);
+        }
 
         let mut avg_mark_signal = 0.0;
         let mut mark_count = 0;
         let mut avg_space_signal = 0.0;
         let mut space_count = 0;
 
-        for i in 0..BITS_PER_CHARACTER {
-            if bits[i] { avg_mark_signal += signals[i]; mark_count += 1; } 
-            else { avg_space_signal += signals[i]; space_count += 1; }
+        for (&bit, &signal) in bits.iter().zip(&signals) {
+            if bit { avg_mark_signal += signal; mark_count += 1; }
+            else { avg_space_signal += signal; space_count += 1; }
         }
 
         if mark_count > 0 { avg_mark_signal /= mark_count as f32; }
         if space_count > 0 { avg_space_signal /= space_count as f32; }
 
         let mut total_divergence = 0.0;
-        for i in 0..BITS_PER_CHARACTER {
-            let avg_for_bit = if bits[i] { avg_mark_signal } else { avg_space_signal };
-            total_divergence += (signals[i] - avg_for_bit).abs() / (avg_for_bit + f32::EPSILON);
-        }
-        let normalized_divergence = total_divergence / BITS_PER_CHARACTER as f32;
-
-        let total_signal: f32 = signals.iter().sum();
-        let total_noise: f32 = noises.iter().sum();
-        let snr = total_signal / (total_noise + f32::EPSILON);
-        let confidence = snr * (1.0 - normalized_divergence).max(0.0);
-        
-        let mut byte = 0u8;
-        for i in 0..8 { if bits[i + 1] { byte |= 1 << i; } }
-        
-        (confidence, byte)
+        for (&bit, &signal) in bits.iter().zip(&signals) {
+            let avg_for_bit = if bit { avg_mark_signal } else { avg_space_signal };
+            total_divergence += (signal - avg_for_bit).abs() / (avg_for_bit + f32::EPSILON);
+        }
+        let normalized_divergence = total_divergence / bits.len() as f32;
+
+        let penalty = (1.0 - normalized_divergence).max(0.0);
+        let noise: f32 = energies.iter().map(|&(mark, space)| mark.min(space)).sum();
+        let snr = signals.iter().sum::<f32>() / (noise + f32::EPSILON);
+        if snr * penalty <= self.config.confidence_threshold {
+            return (0.0, bits);
+        }
+        let reliability = llrs.iter().map(|llr| llr.abs()).sum::<f32>() / llrs.len() as f32;
+        (reliability * penalty, bits)
     }
-}
 
-impl CodecTrait for SonarCodec {
-    fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+    /// Analyzes the frame starting at `signal()[pos]`, returning its confidence and the bytes it carries.
+    fn analyze_frame(&self, pos: usize) -> (f32, Vec<u8>) {
+        if self.modem.frames_symbols() {
+            self.analyze_symbol_frame(pos)
+        } else {
+            let (confidence, byte) = self.analyze_character_frame(pos);
+            (confidence, vec![byte])
+        }
+    }
+
+    /// Primes the modem with the audio in front of a frame starting at `pos`.
+    fn prime_at(&self, pos: usize) {
+        // Symbol frames carry data up to their edges, so there is no mark to prime with
+        let preceding = if self.modem.frames_symbols() { &[][..] } else { &self.signal()[pos - self.lookback()..pos] };
+        self.modem.prime(preceding);
+    }
+
+    /// Primes the modem, then analyzes the frame starting at `pos`.
+    fn probe_frame(&self, pos: usize) -> (f32, Vec<u8>) {
+        self.prime_at(pos);
+        self.analyze_frame(pos)
+    }
+
+    /// How clearly the bits of a frame starting at `pos` stand out: the summed difference
+    /// between their mark and space energies. It peaks when the symbol windows line up with
+    /// the symbols sent, and falls off as they slide onto their neighbours.
+    fn frame_contrast(&self, pos: usize) -> f32 {
+        self.prime_at(pos);
+        self.analyze_bits(pos, self.decided_symbols())
+            .map_or(0.0, |energies| energies.iter().map(|&(mark, space)| (mark - space).abs()).sum())
+    }
+
+    /// Tries every frame start in `start..start + window`, returning the most confident
+    /// frame as `(confidence, bytes, start)`.
+    fn search_frame(&self, start: usize, window: usize) -> (f32, Vec<u8>, usize) {
+        let mut best = (0.0, Vec::new(), 0);
+        for pos in start..start + window {
+            let (confidence, bytes) = self.probe_frame(pos);
+            if confidence > best.0 {
+                best = (confidence, bytes, pos);
+            }
+        }
+        best
+    }
+
+    /// Matched filter for the sync word: correlates the LLRs of the bits starting at `pos`
+    /// with `sync_bits` as ±1 chips (+1 for a mark).
+    ///
+    /// The correlation is normalized by the summed |LLR|, so a clean match comes to 1 and
+    /// scores the same way frames do. A flipped chip takes off twice its share of the total,
+    /// as much as the receiver was sure of it: a Barker 13 word gets past
+    /// [`MIN_SYNC_CORRELATION`] with one chip sent wrong and another the noise only just
+    /// tipped over. Noise gets there too now and then, on a few loud chips, but with more
+    /// than [`MAX_SYNC_ERRORS`] of the others wrong.
+    fn sync_confidence(&self, pos: usize, sync_bits: &[bool]) -> f32 {
+        self.prime_at(pos);
+        let Some(energies) = self.analyze_bits(pos, sync_bits.len() / self.modem.bits_per_symbol()) else {
+            return 0.0;
+        };
+        let (confidence, _) = self.frame_confidence(&energies);
+        let llrs = bit_llrs(&energies);
+        let correlation: f32 = llrs.iter().zip(sync_bits).map(|(&llr, &mark)| if mark { llr } else { -llr }).sum();
+        let reliability: f32 = llrs.iter().map(|llr| llr.abs()).sum();
+        let correlation = correlation / (reliability + f32::EPSILON);
+        let errors = llrs.iter().zip(sync_bits).filter(|&(&llr, &mark)| (llr > 0.0) != mark).count();
+        if correlation < MIN_SYNC_CORRELATION || errors > MAX_SYNC_ERRORS { 0.0 } else { confidence * correlation }
+    }
+
+    /// Looks for the sync word at every offset in `start..start + window`, returning the
+    /// best match as `(confidence, start)`.
+    fn search_sync(&self, start: usize, window: usize, sync_bits: &[bool]) -> (f32, usize) {
+        (start..start + window)
+            .map(|pos| (self.sync_confidence(pos, sync_bits), pos))
+            .fold((0.0, 0), |best, candidate| if candidate.0 > best.0 { candidate } else { best })
+    }
+
+    /// Early-late gate: decides the frame expected at `pos` and measures how far off that is.
+    ///
+    /// Probing the frame's contrast a quarter symbol to each side shows which way its peak
+    /// lies. The returned error, between -1 (the peak is earlier) and 1 (later), is the
+    /// normalized difference of the two, roughly in units of `timing_gate()`.
+    fn track_frame(&self, pos: usize) -> (f32, Vec<u8>, f32) {
+        let gate = self.timing_gate();
+        let early = self.frame_contrast(pos - gate);
+        let late = self.frame_contrast(pos + gate);
+        let (confidence, bytes) = self.probe_frame(pos);
+        let error = if early + late > 0.0 { (late - early) / (late + early) } else { 0.0 };
+        (confidence, bytes, error)
+    }
+
+    /// Hands the `symbols` decided from `pos` on back to the modem, see [`ModemTrait::learn`],
+    /// and follows the signal's level with them. A new signal starts from their level.
+    fn learn_at(&mut self, pos: usize, symbols: usize) {
+        self.prime_at(pos);
+        if let Some(energies) = self.analyze_bits(pos, symbols) {
+            let (_, bits) = self.frame_confidence(&energies);
+            self.modem.learn(&energies, &bits);
+            let level = energies.iter().map(|&(mark, space)| mark.max(space)).sum::<f32>() / energies.len() as f32;
+            self.signal_level = match self.is_receiving {
+                true => self.signal_level + LEVEL_GAIN * (level - self.signal_level),
+                false => level,
+            };
+        }
+    }
+
+    fn analyze_character_frame(&self, pos: usize) -> (f32, u8) {
+        let bits_per_character = self.bits_per_character();
+        let symbols = bits_per_character.div_ceil(self.modem.bits_per_symbol());
+        let Some(mut energies) = self.analyze_bits(pos, symbols) else {
+            return (0.0, 0);
+        };
+        // Only the start, data, parity and first stop bit count; the rest is idle filler
+        energies.truncate(bits_per_character);
+
+        let (confidence, bits) = self.frame_confidence(&energies);
+        match self.config.framing().deframe(&bits) {
+            Some((byte, true)) => (confidence, byte),
+            Some((byte, false)) => (confidence * Self::parity_penalty(&energies), byte),
+            None => (0.0, 0),
+        }
+    }
+
+    /// Share of its confidence a character failing its parity check keeps: twice the chance its
+    /// least reliable bit was flipped. A character whose bits are all clear was received wrong and
+    /// keeps next to nothing, while one with a marginal bit keeps most of it, so a neighbouring
+    /// offset reading that bit right can still win the search.
+    fn parity_penalty(energies: &[(f32, f32)]) -> f32 {
+        let weakest = bit_llrs(energies).iter().map(|llr| llr.abs()).fold(f32::INFINITY, f32::min);
+        2.0 / (1.0 + weakest.exp())
+    }
+
+    /// Analyzes a symbol frame: a length byte followed by that many payload bytes.
+    ///
+    /// Bytes are packed LSB first, like UART characters. A length of zero or beyond the
+    /// symbol's capacity (the all-mark leader reads as 0xFF) marks the frame as invalid.
+    fn analyze_symbol_frame(&self, pos: usize) -> (f32, Vec<u8>) {
+        let Some(energies) = self.analyze_bits(pos, 1) else {
+            return (0.0, Vec::new());
+        };
+        let (confidence, bits) = self.frame_confidence(&energies);
+
+        let bytes: Vec<u8> = bits.chunks_exact(8)
+            .map(|byte_bits| byte_bits.iter().rev().fold(0u8, |acc, &bit| (acc << 1) | bit as u8))
+            .collect();
+        let len = bytes[0] as usize;
+        if len == 0 || len > self.bytes_per_symbol_frame() { return (0.0, Vec::new()); }
+
+        (confidence, bytes[1..=len].to_vec())
+    }
+
+    /// Bits sent for the sync word, padded with marks to whole symbols, then one all-mark
+    /// symbol so the first frame has a phase reference in front like every other one.
+    ///
+    /// +1 chips go out as spaces, so the words open with a run of spaces that stands out
+    /// from the all-mark preamble. Empty without a sync word.
+    fn sync_bits(&self) -> Vec<bool> {
+        let Some(word) = self.config.sync_word else { return Vec::new() };
+        let bits_per_symbol = self.modem.bits_per_symbol();
+        let mut bits: Vec<bool> = word.chips().map(|chip| !chip).collect();
+        bits.resize((bits.len().div_ceil(bits_per_symbol) + 1) * bits_per_symbol, true);
+        bits
+    }
+
+    /// Bits opening every transmission: the all-mark preamble, then the sync word.
+    fn leader_bits(&self) -> Vec<bool> {
+        let bits_per_frame = match self.modem.frames_symbols() {
+            true => self.modem.bits_per_symbol(),
+            false => self.symbols_per_character() * self.modem.bits_per_symbol(),
+        };
+        let mut bits = vec![true; self.config.preamble_chars * bits_per_frame];
+        bits.extend(self.sync_bits());
+        bits
+    }
+
+    /// Bits of the frames carrying `payload`: every byte in a character, or as many as fit
+    /// in every symbol frame.
+    fn frame_bits(&self, payload: &[u8]) -> Result<Vec<bool>, Box<dyn Error>> {
         let mut bitstream = Vec::new();
-        for _ in 0..(LEADER_TONE_CHARS * BITS_PER_CHARACTER) { bitstream.push(true); }
+        if self.modem.frames_symbols() {
+            // Every symbol is a frame of its own: [length, bytes...], zero padded
+            let bits_per_symbol = self.modem.bits_per_symbol();
+            let capacity = self.bytes_per_symbol_frame();
+            if capacity == 0 { return Err("modem symbols are too small to carry a symbol frame".into()); }
+
+            for chunk in payload.chunks(capacity) {
+                let mut frame = vec![chunk.len() as u8];
+                frame.extend_from_slice(chunk);
+                frame.resize(capacity + 1, 0);
+                let start = bitstream.len();
+                for byte in frame {
+                    for i in 0..8 { bitstream.push((byte >> i) & 1 == 1); }
+                }
+                bitstream.resize(start + bits_per_symbol, true);
+            }
+            return Ok(bitstream);
+        }
+
+        // Every character fills a whole number of symbols; spare bits extend the stop bits
+        let bits_per_character = self.symbols_per_character() * self.modem.bits_per_symbol();
+        let framing = self.config.framing();
         for &byte in payload {
-            bitstream.push(false);
-            for i in 0..8 { bitstream.push((byte >> i) & 1 == 1); }
-            bitstream.push(true);
+            let start = bitstream.len();
+            bitstream.extend(framing.frame(byte));
+            bitstream.resize(start + bits_per_character, true);
         }
-        self.modem.modulate(&bitstream)
+        Ok(bitstream)
+    }
+
+    /// Modulates `bits` on `streamer`, carrying on where it left off. With `characters`, the
+    /// bits are whole characters, and 1.5 stop bits end halfway through the last one.
+    fn stream_bits(&self, streamer: &mut dyn StreamModulator, bits: &[bool], characters: bool) -> Result<Vec<f32>, Box<dyn Error>> {
+        let samples_per_bit = self.modem.modulate(&[true])?.len();
+        if !(characters && self.has_half_stop_bit()) {
+            let mut signal = vec![0.0; bits.len() * samples_per_bit];
+            let written = streamer.fill(&mut bits.iter().copied(), &mut signal);
+            signal.truncate(written);
+            return Ok(signal);
+        }
+
+        let bits_per_character = self.symbols_per_character(); // A bit per symbol
+        let samples_per_character = (2 * bits_per_character - 1) * samples_per_bit / 2;
+        let mut signal = vec![0.0; bits.len().div_ceil(bits_per_character) * samples_per_character];
+        let mut written = 0;
+        for character in bits.chunks(bits_per_character) {
+            written += streamer.fill(&mut character.iter().copied(), &mut signal[written..written + samples_per_character]);
+            streamer.end_symbol();
+        }
+        signal.truncate(written);
+        Ok(signal)
+    }
+
+    /// Modulates the payload: the preamble and sync word, then every byte in a character or symbol frame.
+    ///
+    /// Modems with a stream modulator send it through that, which lets characters end
+    /// halfway through their last stop bit (1.5 stop bits) without the carrier jumping.
+    fn modulate_payload(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let frames = self.frame_bits(payload)?;
+        let Some(mut streamer) = self.modem.streamer() else {
+            let mut bitstream = self.leader_bits();
+            bitstream.extend(frames);
+            return self.modem.modulate(&bitstream);
+        };
+        let mut signal = self.stream_bits(&mut *streamer, &self.leader_bits(), false)?;
+        signal.extend(self.stream_bits(&mut *streamer, &frames, true)?);
+        // Whatever bits are left over end up in a last, padded symbol
+        streamer.finish();
+        let mut tail = vec![0.0; self.modem.modulate(&[true])?.len()];
+        let written = streamer.fill(&mut std::iter::empty(), &mut tail);
+        signal.extend_from_slice(&tail[..written]);
+        Ok(signal)
+    }
+
+    /// Samples opening every transmission before its first frame: the all-mark preamble and the sync word.
+    fn preamble_samples(&self) -> usize {
+        let symbols = if self.modem.frames_symbols() { 1 } else { self.symbols_per_character() };
+        let sync_symbols = self.sync_bits().len() / self.modem.bits_per_symbol();
+        (self.samples_per_symbol() * (self.config.preamble_chars * symbols + sync_symbols) as f32).round() as usize
+    }
+
+    /// The buffered audio as the modem gets to analyze it: equalized, once the equalizer is trained.
+    fn signal(&self) -> &[f32] {
+        match self.equalizing {
+            true => &self.equalized_buffer,
+            false => &self.audio_buffer,
+        }
+    }
+
+    /// Runs the buffered audio through the equalizer again, from `from` on.
+    fn refresh_equalized(&mut self, from: usize) {
+        if let Some(equalizer) = &self.equalizer {
+            let from = from.min(self.equalized_buffer.len());
+            self.equalized_buffer.truncate(from);
+            self.equalized_buffer.extend(equalizer.equalize(&self.audio_buffer, from..self.audio_buffer.len()));
+            if self.equalizing {
+                self.invalidate_tones(from);
+            }
+        }
+    }
+
+    /// Switches `signal()` between the raw and the equalized audio.
+    fn set_equalizing(&mut self, equalizing: bool) {
+        if self.equalizing != equalizing {
+            self.equalizing = equalizing;
+            self.invalidate_tones(0);
+        }
+    }
+
+    /// Measures the tones over the windows of `signal()` that arrived or changed since last time.
+    fn update_tones(&mut self) {
+        let signal = if self.equalizing { &self.equalized_buffer } else { &self.audio_buffer };
+        if let Some(bank) = &mut self.tone_bank {
+            bank.update(signal);
+        }
+    }
+
+    /// Forgets the tone energies of `signal()` from `from` on, which changed.
+    fn invalidate_tones(&mut self, from: usize) {
+        if let Some(bank) = &mut self.tone_bank {
+            bank.invalidate(from);
+        }
+    }
+
+    /// Drops the oldest `len` samples of buffered audio.
+    fn drain_audio(&mut self, len: usize) {
+        self.audio_buffer.drain(..len);
+        self.buffer_start += len;
+        if self.equalizer.is_some() {
+            self.equalized_buffer.drain(..len);
+        }
+        if let Some(bank) = &mut self.tone_bank {
+            bank.drain(len);
+        }
+    }
+
+    /// Stops receiving the current signal, going back to searching for the next one.
+    fn drop_signal(&mut self, reason: &str) {
+        self.stats = self.stats();
+        warn!("--- SIGNAL LOST ({}) --- {} frames, {} resyncs, clock offset {:+.1} ppm", reason, self.stats.frames, self.stats.resyncs, self.stats.clock_offset_ppm);
+        self.is_receiving = false;
+        // The next transmission retrains from its own preamble, and times itself from scratch
+        self.set_equalizing(false);
+        self.clock_drift = 0.0;
+    }
+
+    /// Rebuilds the frame carrying `bytes`, as sent, returning its samples. With `restart` it
+    /// opens a new signal instead, returned from its preamble on.
+    ///
+    /// A stream modulator carries the modem's phase from one frame to the next, so only the
+    /// new frame is modulated. Modems without one modulate every byte decided so far again.
+    fn rebuild_frame(&mut self, bytes: &[u8], restart: bool) -> Result<Vec<f32>, Box<dyn Error>> {
+        if restart {
+            self.received.clear();
+            self.reference = self.modem.streamer();
+            self.reference_len = 0;
+        }
+        self.received.extend_from_slice(bytes);
+        let samples = match self.reference.take() {
+            Some(mut streamer) => {
+                let mut samples = match restart {
+                    true => self.stream_bits(&mut *streamer, &self.leader_bits(), false)?,
+                    false => Vec::new(),
+                };
+                samples.extend(self.stream_bits(&mut *streamer, &self.frame_bits(bytes)?, true)?);
+                self.reference = Some(streamer);
+                samples
+            }
+            None => self.modulate_payload(&self.received)?.split_off(self.reference_len),
+        };
+        self.reference_len += samples.len();
+        Ok(samples)
+    }
+
+    /// Trains the equalizer on a frame just decided at `frame_start`, and on the preamble in
+    /// front of it when the frame opens the signal.
+    ///
+    /// The reference is the transmission rebuilt from every byte decided so far, so it picks
+    /// up the modem's phase where the previous frame left it. It stays lined up with where
+    /// the signal started rather than with every frame's own (coarser) detected position;
+    /// the taps absorb what is left of the offset, and follow it as the clocks drift.
+    ///
+    /// The taps are only applied once a second frame lands where the reference expects it.
+    /// A frame that does not means the opening one was noise mistaken for a character, or
+    /// that frames were missed: training starts over from that frame and its own preamble.
+    fn train_equalizer(&mut self, frame_start: usize, bytes: &[u8], opens_signal: bool) -> Result<(), Box<dyn Error>> {
+        let Some(reach) = self.equalizer.as_ref().map(|e| e.taps().len() / 2) else { return Ok(()) };
+        let frame_end = self.buffer_start + frame_start + self.samples_per_frame();
+
+        let mut reference = Vec::new();
+        if !opens_signal {
+            reference = self.rebuild_frame(bytes, false)?;
+        }
+        let lines_up = !opens_signal && (self.signal_start + self.reference_len).abs_diff(frame_end) <= reach;
+        let trained_from = match lines_up {
+            true => 0,
+            false => {
+                reference = self.rebuild_frame(bytes, true)?;
+                self.signal_start = frame_end.saturating_sub(reference.len());
+                if let Some(equalizer) = &mut self.equalizer { equalizer.reset(); }
+                // Everything from the end of the fade-in on
+                self.config.fade_samples.min(reference.len())
+            }
+        };
+        self.set_equalizing(lines_up);
+        let reference_start = self.signal_start + self.reference_len - reference.len();
+        let Some(offset) = (reference_start + trained_from).checked_sub(self.buffer_start) else {
+            return Ok(()); // Already drained
+        };
+
+        if let Some(equalizer) = &mut self.equalizer {
+            let error = equalizer.train(&self.audio_buffer, &reference[trained_from..], offset);
+            debug!("Equalizer trained on {} samples, MSE {:.5}", reference.len() - trained_from, error);
+        }
+        self.refresh_equalized(offset);
+        Ok(())
+    }
+}
+
+impl CodecTrait for SonarCodec {
+    fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        let mut signal = self.modulate_payload(payload)?;
+        apply_fade(&mut signal, self.config.fade_samples);
+        Ok(signal)
     }
 
     fn decode(&mut self, samples: &[f32]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
         self.audio_buffer.extend_from_slice(samples);
-        let samples_per_char = self.samples_per_character();
+        // The last outputs lacked the samples that just arrived
+        let settled = self.audio_buffer.len() - samples.len();
+        self.refresh_equalized(settled.saturating_sub(self.equalizer.as_ref().map_or(0, |e| e.taps().len() / 2)));
+        let samples_per_frame = self.samples_per_frame();
         let mut found_bytes = Vec::new();
-        
-        let mut current_search_offset = 0;
+
+        // Keep one symbol of history in front of every candidate, plus room for the early
+        // timing probe. The equalizer trains on the preamble of a new signal, so keep that
+        // too (and its taps' reach)
+        let history = self.lookback() + self.timing_gate()
+            + self.equalizer.as_ref().map_or(0, |e| self.preamble_samples() + e.taps().len());
+        let mut current_search_offset = history;
+
+        // Searching for a signal, or for a frame the timing loop lost, tries every offset over one and a half symbols
+        let search_window_size = (self.samples_per_symbol() * 1.5).round() as usize;
+        let sync_bits = self.sync_bits();
+        let sync_symbols = sync_bits.len() / self.modem.bits_per_symbol();
 
         loop {
-            let search_window_size = if self.is_receiving {
-                // TRACKING MODE: Narrow search around the expected position
-                (self.samples_per_bit() * 0.5).round() as usize
-            } else {
-                // SEARCHING MODE: Wide search to find the first signal
-                (self.samples_per_bit() * 1.5).round() as usize
+            // With a sync word, a new signal is only ever picked up by it
+            let hunting_sync = !self.is_receiving && !sync_bits.is_empty();
+            // Tracking only looks a gate's width past the expected frame
+            let reach = if self.is_receiving { self.timing_gate() } else { search_window_size };
+            let span = match hunting_sync {
+                true => (self.samples_per_symbol() * sync_symbols as f32).round() as usize,
+                false => samples_per_frame,
             };
-
-            let search_area_end = current_search_offset + search_window_size + samples_per_char;
-            if search_area_end > self.audio_buffer.len() {
-                break; // Not enough data to conduct a full search from our current position
+            if current_search_offset + reach + span > self.audio_buffer.len() {
+                break; // Not enough data to analyze the frame from our current position
             }
+            self.update_tones();
 
-            let mut best_confidence = 0.0;
-            let mut best_byte = 0;
-            let mut best_frame_start_pos = 0;
-
-            for offset in 0..search_window_size {
-                let pos = current_search_offset + offset;
-                let frame_window = &self.audio_buffer[pos..(pos + samples_per_char)];
-                let (confidence, byte) = self.analyze_character_frame(frame_window);
-                if confidence > best_confidence {
-                    best_confidence = confidence;
-                    best_byte = byte;
-                    best_frame_start_pos = pos;
+            if hunting_sync {
+                let (confidence, sync_start) = self.search_sync(current_search_offset, search_window_size, &sync_bits);
+                if confidence > self.config.min_mean_llr {
+                    warn!("--- SIGNAL DETECTED (Sync word, confidence: {:.2}) ---", confidence);
+                    self.learn_at(sync_start, sync_symbols);
+                    self.is_receiving = true;
+                    self.stats = ReceiveStats::default();
+                    self.missed = 0;
+                    // The first frame starts right where the sync word ends
+                    let first_frame = sync_start as f32 + sync_symbols as f32 * self.received_samples_per_symbol();
+                    current_search_offset = first_frame.round() as usize;
+                    self.frame_phase = first_frame - current_search_offset as f32;
+                } else {
+                    self.drain_audio(current_search_offset + search_window_size - history);
+                    current_search_offset = history;
                 }
+                continue;
             }
 
-            if best_confidence > self.config.confidence_threshold {
+            // Once locked, the timing loop says where the next frame starts; only search
+            // the offsets around it when the frame there does not hold up
+            let mut timing_error = 0.0;
+            let mut search_start = current_search_offset;
+            let mut resyncing = false;
+            let (best_confidence, best_bytes, best_frame_start_pos) = match self.is_receiving {
+                true => {
+                    let (confidence, bytes, error) = self.track_frame(current_search_offset);
+                    timing_error = error;
+                    if confidence > self.config.min_mean_llr {
+                        (confidence, bytes, current_search_offset)
+                    } else {
+                        debug!("Timing loop lost the frame (confidence {:.2}), searching around it", confidence);
+                        resyncing = true;
+                        // From the early probe on: any earlier, the previous frame's last symbol
+                        // could pass for a start bit with the phase reference upside down
+                        search_start = current_search_offset - self.timing_gate();
+                        if search_start + search_window_size + samples_per_frame > self.audio_buffer.len() {
+                            break; // Not enough data to conduct a full search from there
+                        }
+                        self.frame_phase = 0.0;
+                        timing_error = 0.0;
+                        self.search_frame(search_start, search_window_size)
+                    }
+                }
+                false => self.search_frame(current_search_offset, search_window_size),
+            };
+
+            if best_confidence > self.config.min_mean_llr {
+                // Frames found by their sync word open the signal too
+                let opens_signal = !self.is_receiving || self.stats.frames == 0;
+                self.train_equalizer(best_frame_start_pos, &best_bytes, opens_signal)?;
+                self.learn_at(best_frame_start_pos, self.decided_symbols());
                 if !self.is_receiving {
                     warn!("--- SIGNAL DETECTED (Confidence: {:.2}) ---", best_confidence);
                     self.is_receiving = true;
+                    self.frame_phase = 0.0;
+                    self.stats = ReceiveStats::default();
                 }
-                info!("CHARACTER FOUND! Byte: 0x{:02X} ('{}'), Confidence: {:.2}", best_byte, if (best_byte as char).is_ascii_graphic() { best_byte as char } else { '.' }, best_confidence);
-                found_bytes.push(best_byte);
+                self.stats.frames += 1;
+                self.stats.resyncs += resyncing as usize;
+                self.missed = 0;
+                for &best_byte in &best_bytes {
+                    info!("CHARACTER FOUND! Byte: 0x{:02X} ('{}'), Confidence: {:.2}", best_byte, if (best_byte as char).is_ascii_graphic() { best_byte as char } else { '.' }, best_confidence);
+                }
+                found_bytes.extend(best_bytes);
 
-                // The next search should start exactly one character's length after this one started.
-                current_search_offset = best_frame_start_pos + samples_per_char;
+                // The next frame starts one frame's length after this one, as stretched by the
+                // clock drift, give or take the share of the timing error the loop corrects.
+                // Corrections that keep pointing the same way are the drift, so it learns from them
+                let correction = TIMING_GAIN * timing_error * self.timing_gate() as f32;
+                self.clock_drift = (self.clock_drift + DRIFT_GAIN * correction / self.frame_length()).clamp(-MAX_DRIFT, MAX_DRIFT);
+                trace!("Timing error {:+.3} of the gate, moving the next frame by {:+.2} samples, drift {:+.1} ppm", timing_error, correction, self.clock_drift * 1e6);
+                let next_frame = best_frame_start_pos as f32 + self.frame_phase + self.frame_length() * (1.0 + self.clock_drift) + correction;
+                current_search_offset = (next_frame.round() as usize).max(history);
+                self.frame_phase = next_frame - current_search_offset as f32;
 
             } else {
                 // No character found in this search window.
-                // We're done for this call to decode().
-                // First, drain the audio we just fruitlessly searched.
-                let drain_end = (current_search_offset + search_window_size).min(self.audio_buffer.len());
-                self.audio_buffer.drain(..drain_end);
-                break;
+                // Drain the audio we just fruitlessly searched (keeping the history) and keep
+                // scanning the rest of the buffer, so the search never falls behind the stream.
+                self.drain_audio(search_start + search_window_size - history);
+                current_search_offset = history;
+
+                // Two frames' worth of nothing: the transmission is over. With a sync word,
+                // go back to waiting for the next one rather than taking noise for frames
+                self.missed += search_window_size;
+                if self.is_receiving && !sync_bits.is_empty() && self.missed > 2 * samples_per_frame {
+                    self.drop_signal("Ended");
+                }
             }
         }
 
-        // If we found at least one character, we must drain the buffer up to where the next character should start.
-        if current_search_offset > 0 {
-             self.audio_buffer.drain(..current_search_offset);
-        }
+        // Drain the buffer up to where the next character should start, keeping the history.
+        self.drain_audio(current_search_offset - history);
 
         Ok(if found_bytes.is_empty() { None } else { Some(found_bytes) })
     }
 
     fn reset_state(&mut self) {
         if self.is_receiving {
-            warn!("--- SIGNAL LOST (Timeout) ---");
-            self.is_receiving = false;
+            self.drop_signal("Timeout");
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::{BPSK, FSK, PulseFilter, QPSK};
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn rtty_roundtrip_with_one_and_a_half_stop_bits() {
+        let config = SonarCodecConfig::rtty(48_000);
+        let mut codec = SonarCodec::new(Box::new(FSK::rtty(48_000)), config);
+        let codes = Ita2::default().encode("RYRY CQ 73");
+
+        let signal = codec.encode(&codes).unwrap();
+        // Leader, then 7.5 bits per character
+        let samples_per_bit = 48_000.0 / 45.45;
+        let expected = (LEADER_TONE_CHARS * 8 + codes.len() * 15 / 2) as f32 * samples_per_bit;
+        assert!((signal.len() as f32 - expected).abs() < samples_per_bit);
+        // The shortened stop bits keep the carrier's phase: no sample jumps further than the
+        // higher tone (2295 Hz) moves in one sample
+        let largest_step = signal.windows(2).map(|pair| (pair[1] - pair[0]).abs()).fold(0.0, f32::max);
+        assert!(largest_step < 2.0 * std::f32::consts::PI * 2_400.0 / 48_000.0, "click of {largest_step}");
+
+        let mut padded = vec![0.0; 3_000];
+        padded.extend(signal);
+        padded.extend(vec![0.0; 3_000]);
+        let decoded: Vec<u8> = padded.chunks(4_096).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+        assert_eq!(Ita2::default().decode(&decoded), "RYRY CQ 73");
+    }
+
+    #[test]
+    fn bpsk_tracks_a_carrier_several_hz_off() {
+        let config = SonarCodecConfig { sync_word: Some(SyncWord::BARKER_13), ..SonarCodecConfig::default() };
+        let payload: Vec<u8> = (0..48u32).map(|i| (i * 53 + 7) as u8).collect();
+        // The transmitter's carrier is 8 Hz high: a quarter turn every 10-bit character at
+        // 300 baud, too much for the phase of the mark in front of it to carry over
+        for pulse in [None, Some(PulseFilter::rrc(1.0, 6, 160))] {
+            let tx = SonarCodec::new(Box::new(BPSK::new(48_000, 1_208.0, 160).with_pulse(pulse.clone())), config);
+            let mut codec = SonarCodec::new(Box::new(BPSK::new(48_000, 1_200.0, 160).with_pulse(pulse.clone())), config);
+
+            let mut received = vec![0.0; 2_000];
+            received.extend(tx.encode(&payload).unwrap());
+            received.extend(vec![0.0; 2_000]);
+            let decoded: Vec<u8> = received.chunks(4_096).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+            assert_eq!(decoded, payload, "shaped: {}", pulse.is_some());
+        }
+    }
+
+    /// `signal` as heard by a receiver whose sample clock runs `ratio` times as fast.
+    fn resample(signal: &[f32], ratio: f64) -> Vec<f32> {
+        (0..(signal.len() as f64 * ratio) as usize)
+            .map(|n| {
+                let t = n as f64 / ratio;
+                let (i, frac) = (t as usize, t.fract() as f32);
+                let next = signal.get(i + 1).copied().unwrap_or(0.0);
+                signal.get(i).map_or(0.0, |&x| x + frac * (next - x))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn timing_loop_follows_clock_drift_over_a_long_transfer() {
+        let config = SonarCodecConfig { baud_rate: 1_200, sync_word: Some(SyncWord::BARKER_13), ..SonarCodecConfig::default() };
+        let mut codec = SonarCodec::new(Box::new(FSK::new(48_000, 1_200.0, 2_200.0, 40)), config);
+        let payload: Vec<u8> = (0..2_000u32).map(|i| (i * 37 + i / 7) as u8).collect();
+        let signal = codec.encode(&payload).unwrap();
+
+        // The receiver's clock runs 300 ppm fast, so the signal slips 6 symbols by the end
+        let mut received = vec![0.0; 2_000];
+        received.extend(resample(&signal, 1.0003));
+        received.extend(vec![0.0; 2_000]);
+
+        let decoded: Vec<u8> = received.chunks(4_096).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+        assert!(decoded == payload, "{} bytes decoded", decoded.len());
+        let stats = codec.stats();
+        assert_eq!(stats.resyncs, 0);
+        assert!((stats.clock_offset_ppm - 300.0).abs() < 50.0, "{} ppm", stats.clock_offset_ppm);
+    }
+
+    #[test]
+    fn clock_drift_does_not_carry_over_to_the_next_transmission() {
+        let config = SonarCodecConfig { baud_rate: 1_200, sync_word: Some(SyncWord::BARKER_13), ..SonarCodecConfig::default() };
+        let mut codec = SonarCodec::new(Box::new(FSK::new(48_000, 1_200.0, 2_200.0, 40)), config);
+        let payload: Vec<u8> = (0..1_000u32).map(|i| (i * 53 + i / 5) as u8).collect();
+
+        // A first transmission from a sound card running 300 ppm slow...
+        let mut received = vec![0.0; 2_000];
+        received.extend(resample(&codec.encode(&payload).unwrap(), 1.0 / 1.0003));
+        received.extend(vec![0.0; 4_000]);
+        let decoded: Vec<u8> = received.chunks(4_096).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+        assert!(decoded == payload, "{} bytes decoded", decoded.len());
+        assert!((codec.stats().clock_offset_ppm + 300.0).abs() < 100.0, "{} ppm", codec.stats().clock_offset_ppm);
+
+        // ...then a short one from a card matching the receiver's clock
+        let mut received = codec.encode(&payload[..20]).unwrap();
+        received.extend(vec![0.0; 4_000]);
+        let decoded: Vec<u8> = received.chunks(4_096).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+        assert_eq!(decoded, &payload[..20]);
+        let stats = codec.stats();
+        assert_eq!((stats.frames, stats.resyncs), (20, 0));
+        assert!(stats.clock_offset_ppm.abs() < 100.0, "{} ppm", stats.clock_offset_ppm);
+    }
+
+    #[test]
+    fn sync_word_rejects_frames_without_it() {
+        let config = SonarCodecConfig { preamble_chars: 2, sync_word: Some(SyncWord::pn(5).unwrap()), ..SonarCodecConfig::default() };
+        let mut codec = SonarCodec::new(Box::new(FSK::default()), config);
+        let unsynced = SonarCodec::new(Box::new(FSK::default()), SonarCodecConfig { sync_word: None, ..config });
+
+        // Characters from a station that sends no sync word, on top of noise, then the real thing
+        let mut received: Vec<f32> = unsynced.encode(b"noise").unwrap();
+        received.extend(vec![0.0; 3_000]);
+        received.extend(codec.encode(b"sync").unwrap());
+        received.extend(vec![0.0; 2_000]);
+        let mut rng = StdRng::seed_from_u64(21);
+        for x in received.iter_mut() {
+            *x += rng.random_range(-0.2..0.2);
         }
+
+        let decoded: Vec<u8> = received.chunks(1_000).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+        assert_eq!(decoded, b"sync");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn characters_failing_parity_are_penalized_by_their_weakest_bit() {
+        let even = UartFraming { data_bits: 7, parity: Parity::Even, ..UartFraming::default() };
+        // 'C' = 100_0011, after a mark to prime on and followed by one
+        let mut bits = vec![true];
+        bits.extend(even.frame(b'C'));
+        bits.push(true);
+        let mut flipped = bits.clone();
+        flipped[9] = !flipped[9]; // Parity bit
+
+        let modem = FSK::default();
+        let clear = modem.modulate(&bits).unwrap();
+        let bit_len = clear.len() / bits.len();
+        // The parity bit with both tones in it, the one sent only just the stronger
+        let mut marginal = clear.clone();
+        let opposite = modem.modulate(&flipped).unwrap();
+        for n in 9 * bit_len..10 * bit_len {
+            marginal[n] = 0.5002 * clear[n] + 0.4998 * opposite[n];
+        }
+
+        for (parity, audio, clears) in [(Parity::Even, &clear, true), (Parity::Odd, &clear, false), (Parity::Odd, &marginal, true)] {
+            let config = SonarCodecConfig { data_bits: 7, parity, ..SonarCodecConfig::default() };
+            let mut codec = SonarCodec::new(Box::new(FSK::default()), config);
+            codec.audio_buffer = audio.clone();
+            let (confidence, byte) = codec.analyze_character_frame(codec.lookback());
+            assert_eq!(confidence > codec.config.min_mean_llr, clears, "{parity:?}: {confidence}");
+            assert_eq!(byte, b'C', "{parity:?}");
+        }
+    }
+
+    #[test]
+    fn tone_bank_only_measures_symbols_as_long_as_its_window() {
+        let mut codec = SonarCodec::new(Box::new(FSK::default()), SonarCodecConfig::default());
+        let bits: Vec<bool> = (0..40).map(|i| (i * 5 + 2) % 7 < 3).collect();
+        codec.audio_buffer = codec.modem.modulate(&bits).unwrap();
+        codec.update_tones();
+        // 1000 ppm stretches every sixth symbol or so to 161 samples, past the bank's 160
+        codec.clock_drift = 1e-3;
+
+        let banked = codec.analyze_bits(37, 30).unwrap();
+        codec.tone_bank = None;
+        let analyzed = codec.analyze_bits(37, 30).unwrap();
+        for (&(mark, space), &(exact_mark, exact_space)) in banked.iter().zip(&analyzed) {
+            assert!((mark - exact_mark).abs() <= 1e-4 * exact_mark.max(exact_space), "{mark} vs {exact_mark}");
+            assert!((space - exact_space).abs() <= 1e-4 * exact_mark.max(exact_space), "{space} vs {exact_space}");
+        }
+    }
+
+    #[test]
+    fn sync_word_survives_a_few_flipped_chips() {
+        let config = SonarCodecConfig { preamble_chars: 2, sync_word: Some(SyncWord::BARKER_13), ..SonarCodecConfig::default() };
+        let mut codec = SonarCodec::new(Box::new(FSK::default()), config);
+        let barker = 0b1_1111_0011_0101;
+
+        // Transmitters whose sync words lost chips on the way, in noise
+        for (flipped, found) in [(0b0_0000_0000_0000, true), (0b0_0000_0010_0000, true), (0b0_0100_0000_1000, false)] {
+            let word = SyncWord::new(barker ^ flipped, 13).unwrap();
+            let damaged = SonarCodec::new(Box::new(FSK::default()), SonarCodecConfig { sync_word: Some(word), ..config });
+            let mut received = vec![0.0; 3_000];
+            received.extend(damaged.encode(b"chips").unwrap());
+            received.extend(vec![0.0; 2_000]);
+            let mut rng = StdRng::seed_from_u64(flipped);
+            for x in received.iter_mut() {
+                *x += rng.random_range(-0.2..0.2);
+            }
+
+            codec.reset_state();
+            let decoded: Vec<u8> = received.chunks(1_000).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+            assert_eq!(decoded == b"chips", found, "{flipped:013b}: {decoded:?}");
+        }
+    }
+
+    #[test]
+    fn noise_after_a_transmission_decodes_to_nothing() {
+        let config = SonarCodecConfig { baud_rate: 1_200, sync_word: Some(SyncWord::BARKER_13), ..SonarCodecConfig::default() };
+        let mut codec = SonarCodec::new(Box::new(QPSK::default()), config);
+
+        // QPSK's losing side is small even in pure noise, so a second of it after the
+        // message scores like frames; only the squelch tells it apart
+        let mut received = vec![0.0; 3_000];
+        received.extend(codec.encode(b"HELLO WORLD").unwrap());
+        received.extend(vec![0.0; 48_000]);
+        let mut rng = StdRng::seed_from_u64(16);
+        for x in received.iter_mut() {
+            *x += rng.random_range(-0.2..0.2);
+        }
+
+        let decoded: Vec<u8> = received.chunks(1_024).filter_map(|c| codec.decode(c).unwrap()).flatten().collect();
+        assert_eq!(decoded, b"HELLO WORLD");
+    }
+
+    #[test]
+    fn confidence_threshold_keeps_scoring_the_signal_to_noise_ratio() {
+        // Bits 9 times as strong as the losing side: an SNR of 9, and a mean |LLR| of 11.3
+        let energies: Vec<(f32, f32)> = (0..10).map(|i| if i % 3 == 0 { (9.0, 1.0) } else { (1.0, 9.0) }).collect();
+        for (confidence_threshold, taken) in [(8.0, true), (10.0, false)] {
+            let config = SonarCodecConfig { confidence_threshold, ..SonarCodecConfig::default() };
+            let codec = SonarCodec::new(Box::new(FSK::default()), config);
+            let (confidence, _) = codec.frame_confidence(&energies);
+            assert_eq!(confidence > 10.0, taken, "{confidence_threshold}: {confidence}");
+        }
+    }
+
+    #[test]
+    fn clean_frames_score_a_finite_confidence() {
+        let codec = SonarCodec::new(Box::new(FSK::default()), SonarCodecConfig::default());
+        // A synthetic frame leaves exactly nothing on the losing side of its bits
+        let energies: Vec<(f32, f32)> = (0..10).map(|i| if i % 3 == 0 { (0.0, 1.0) } else { (1.0, 0.0) }).collect();
+        let (confidence, bits) = codec.frame_confidence(&energies);
+        assert!(confidence.is_finite() && confidence > 4.0, "{confidence}");
+        assert_eq!(bits, energies.iter().map(|&(mark, space)| mark > space).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,234 @@
+// * Asynchronous PPP framing (RFC 1662): flag delimited, byte stuffed frames with an FCS-16
+
+use std::error::Error;
+
+use super::hdlc::fcs;
+use super::framed::{ByteFraming, FramedCodec};
+use super::{CodecTrait, FrameKind};
+
+/// Flag opening and closing every RFC 1662 frame.
+pub const PPP_FLAG: u8 = 0x7E;
+/// Control escape: the next byte was sent XORed with `0x20`.
+pub const PPP_ESCAPE: u8 = 0x7D;
+
+/// Async PPP framer: delimits frames with flags and escapes every byte that could be
+/// mistaken for one, so payloads may hold any byte value.
+///
+/// Frames carry the same FCS-16 as HDLC, low byte first. Besides the delimiters and the
+/// escape itself, the control characters (0x00 to 0x1F) set in the async control character
+/// map (ACCM) are escaped, so modems and terminals that eat XON/XOFF or the like leave
+/// them alone. The default map escapes all 32 of them, as RFC 1662 does until the link
+/// negotiates otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AsyncPpp {
+    start_delim: u8,
+    end_delim: u8,
+    accm: u32, // Bit n set: escape byte n
+}
+
+impl Default for AsyncPpp {
+    fn default() -> Self {
+        Self { start_delim: PPP_FLAG, end_delim: PPP_FLAG, accm: u32::MAX }
+    }
+}
+
+impl AsyncPpp {
+    /// Creates a framer for the given frame kind.
+    ///
+    /// Only [`FrameKind::AsyncPPP`] is byte stuffed, and neither delimiter may be the escape.
+    pub fn new(kind: FrameKind) -> Result<Self, Box<dyn Error>> {
+        match kind {
+            FrameKind::AsyncPPP { start_delim, end_delim } if start_delim == PPP_ESCAPE || end_delim == PPP_ESCAPE => {
+                Err(format!("0x{PPP_ESCAPE:02X} is the PPP escape and cannot delimit frames").into())
+            }
+            FrameKind::AsyncPPP { start_delim, end_delim } => Ok(Self { start_delim, end_delim, ..Self::default() }),
+            other => Err(format!("{other:?} is not a byte-stuffed frame kind").into()),
+        }
+    }
+
+    /// Sets the async control character map: bit n set escapes byte n.
+    pub fn with_accm(mut self, accm: u32) -> Self {
+        self.accm = accm;
+        self
+    }
+
+    /// Whether `byte` is sent escaped.
+    fn escapes(&self, byte: u8) -> bool {
+        byte == self.start_delim
+            || byte == self.end_delim
+            || byte == PPP_ESCAPE
+            || (byte < 0x20 && self.accm & (1 << byte) != 0)
+    }
+
+    /// Frames `data`: the start delimiter, the escaped frame and its FCS, then the end delimiter.
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(data.len() + data.len() / 4 + 6);
+        bytes.push(self.start_delim);
+        for byte in data.iter().copied().chain(fcs(data).to_le_bytes()) {
+            if self.escapes(byte) {
+                bytes.extend([PPP_ESCAPE, byte ^ 0x20]);
+            } else {
+                bytes.push(byte);
+            }
+        }
+        bytes.push(self.end_delim);
+        bytes
+    }
+
+    /// Creates a receiver for frames sent by this framer.
+    pub fn deframer(&self) -> AsyncPppDeframer {
+        AsyncPppDeframer { ppp: *self, bytes: Vec::new(), escaped: false, in_frame: false }
+    }
+}
+
+/// Byte-by-byte async PPP receiver.
+///
+/// Splits the stream on the delimiters, undoes the escapes and only releases frames whose
+/// FCS checks out. Control characters in the ACCM that arrive unescaped were inserted on the
+/// way and are dropped; an escape followed by a delimiter aborts the frame in progress.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsyncPppDeframer {
+    ppp: AsyncPpp,
+    bytes: Vec<u8>, // Unescaped bytes since the frame started
+    escaped: bool,  // Whether the last byte was the escape
+    in_frame: bool, // Whether a start delimiter has been seen since the last frame or abort
+}
+
+impl AsyncPppDeframer {
+    /// Shortest frame worth checking: one byte and the FCS.
+    const MIN_FRAME_LEN: usize = 3;
+    /// Longest frame kept before giving up on a missing end delimiter.
+    const MAX_FRAME_LEN: usize = 1024;
+
+    /// Feeds one received byte, returning a frame (without its FCS) when one completes.
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        let AsyncPpp { start_delim, end_delim, .. } = self.ppp;
+        if byte == end_delim || byte == start_delim {
+            let aborted = std::mem::replace(&mut self.escaped, false);
+            let frame = (byte == end_delim && self.in_frame && !aborted).then(|| self.take_frame()).flatten();
+            self.bytes.clear();
+            // With a single flag, the one closing a frame also opens the next
+            self.in_frame = byte == start_delim;
+            return frame;
+        }
+        if !self.in_frame || (byte < 0x20 && self.ppp.accm & (1 << byte) != 0) {
+            return None;
+        }
+
+        if byte == PPP_ESCAPE {
+            self.escaped = true;
+        } else {
+            self.bytes.push(if std::mem::replace(&mut self.escaped, false) { byte ^ 0x20 } else { byte });
+            if self.bytes.len() > Self::MAX_FRAME_LEN {
+                self.bytes.clear();
+                self.in_frame = false;
+            }
+        }
+        None
+    }
+
+    /// Drops the frame in progress, e.g. when the link below lost its signal.
+    pub fn reset(&mut self) {
+        self.bytes.clear();
+        self.escaped = false;
+        self.in_frame = false;
+    }
+
+    /// Checks the FCS of the collected bytes.
+    fn take_frame(&self) -> Option<Vec<u8>> {
+        if self.bytes.len() < Self::MIN_FRAME_LEN {
+            return None;
+        }
+        let (data, trailer) = self.bytes.split_at(self.bytes.len() - 2);
+        (fcs(data) == u16::from_le_bytes([trailer[0], trailer[1]])).then(|| data.to_vec())
+    }
+}
+
+/// Sends every payload as an async PPP frame over a byte codec, such as the UART characters
+/// of [`SonarCodec`](super::SonarCodec).
+///
+/// The receiver gets back exactly the payloads sent, binary or not, and nothing from frames
+/// that arrived damaged. A [`FramedCodec`] with [`ByteFraming::AsyncPpp`] underneath, so
+/// payloads longer than 256 bytes go out as several frames.
+pub struct PppCodec<C: CodecTrait>(FramedCodec<C>);
+
+impl<C: CodecTrait> PppCodec<C> {
+    pub fn new(codec: C, ppp: AsyncPpp) -> Self {
+        Self(FramedCodec::new(codec, ByteFraming::AsyncPpp(ppp)))
+    }
+
+    /// The byte codec the frames travel over.
+    pub fn codec(&self) -> &C {
+        self.0.codec()
+    }
+
+    /// Feeds audio to the receiver, returning every new frame with a valid FCS.
+    pub fn decode_frames(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        self.0.decode_frames(samples)
+    }
+}
+
+impl<C: CodecTrait> CodecTrait for PppCodec<C> {
+    fn encode(&self, payload: &[u8]) -> Result<Vec<f32>, Box<dyn Error>> {
+        self.0.encode(payload)
+    }
+
+    fn decode(&mut self, samples: &[f32]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        self.0.decode(samples)
+    }
+
+    /// Resets the codec below and drops the frame in progress.
+    fn reset_state(&mut self) {
+        self.0.reset_state();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modem::FSK;
+    use crate::stack::datalink::{SonarCodec, SonarCodecConfig};
+
+    #[test]
+    fn async_ppp_escapes_any_byte() {
+        let ppp = AsyncPpp::new(FrameKind::AsyncPPP { start_delim: PPP_FLAG, end_delim: PPP_FLAG }).unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        let line = ppp.encode(&data);
+        assert!(line[1..line.len() - 1].iter().all(|&b| b >= 0x20 && b != PPP_FLAG));
+
+        // Flow control characters slipped in on the way are dropped; an escape followed by
+        // a flag aborts the frame in progress
+        let mut stream = vec![0x11, PPP_FLAG, 0x41, PPP_ESCAPE, PPP_FLAG];
+        for (i, &byte) in line.iter().enumerate() {
+            stream.push(byte);
+            if i % 50 == 7 {
+                stream.push(0x13);
+            }
+        }
+        let mut deframer = ppp.deframer();
+        let frames: Vec<Vec<u8>> = stream.into_iter().filter_map(|b| deframer.push(b)).collect();
+        assert_eq!(frames, vec![data.clone()]);
+        let single: Vec<Vec<u8>> = ppp.encode(b"!").into_iter().filter_map(|b| deframer.push(b)).collect();
+        assert_eq!(single, vec![b"!".to_vec()]);
+
+        // With an empty ACCM only the flag and the escape are escaped
+        let clear = ppp.with_accm(0).encode(&data);
+        assert_eq!(clear.len(), data.len() + 2 + 2 + 2);
+        assert!(AsyncPpp::new(FrameKind::AsyncPPP { start_delim: PPP_ESCAPE, end_delim: PPP_FLAG }).is_err());
+    }
+
+    #[test]
+    fn ppp_codec_carries_binary_payloads_over_uart_characters() {
+        let new_codec = || PppCodec::new(SonarCodec::new(Box::new(FSK::default()), SonarCodecConfig::default()), AsyncPpp::default());
+        let tx = new_codec();
+        let mut rx = new_codec();
+
+        let payload = [0x00, b'\n', PPP_FLAG, PPP_ESCAPE, 0xFF, 0x11, 0x13, b'o', b'k'];
+        let mut signal = vec![0.0; 1_013];
+        signal.extend(tx.encode(&payload).unwrap());
+        signal.extend(vec![0.0; 4_000]);
+
+        let frames: Vec<Vec<u8>> = signal.chunks(1_024).flat_map(|c| rx.decode_frames(c).unwrap()).collect();
+        assert_eq!(frames, vec![payload.to_vec()]);
+    }
+}
@@ -0,0 +1,90 @@
+// * Sync words: the chip patterns marking where the frames of a transmission begin
+
+use std::error::Error;
+
+/// A ±1 sequence marking the exact sample where the frames of a transmission begin.
+///
+/// A good sync word looks nothing like shifted copies of itself, so a matched filter sees a
+/// single sharp peak where the whole word lines up, and noise or a steady tone rarely
+/// matches it at all. Barker codes have the lowest sidelobes there are; maximal-length (PN)
+/// sequences come in more lengths.
+///
+/// Chips are +1 as `true`, and at most 64 long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncWord {
+    chips: u64, // The first chip in the highest of the `len` low bits
+    len: u8,
+}
+
+impl SyncWord {
+    /// Barker code of length 13: `+ + + + + - - + + - + - +`.
+    pub const BARKER_13: Self = Self { chips: 0b1_1111_0011_0101, len: 13 };
+
+    /// Any sequence of 1 to 64 chips, the first one in the highest of the `len` low bits of `chips`.
+    pub fn new(chips: u64, len: usize) -> Result<Self, Box<dyn Error>> {
+        if !(1..=64).contains(&len) {
+            return Err(format!("A sync word has 1 to 64 chips, got {}", len).into());
+        }
+        if len < 64 && chips >> len != 0 {
+            return Err(format!("0x{:X} does not fit in {} chips", chips, len).into());
+        }
+        Ok(Self { chips, len: len as u8 })
+    }
+
+    /// Maximal-length sequence of `2^degree - 1` chips, for degrees 2 to 6 (3 to 63 chips),
+    /// from a linear feedback shift register started at all ones.
+    pub fn pn(degree: u32) -> Result<Self, Box<dyn Error>> {
+        // Feedback taps of a primitive polynomial for each degree
+        let taps: u32 = match degree {
+            2 | 3 | 4 | 6 => 0b11,
+            5 => 0b101,
+            _ => return Err(format!("PN sync words have a degree of 2 to 6, got {}", degree).into()),
+        };
+        let len = (1 << degree) - 1;
+        let mut state: u32 = (1 << degree) - 1;
+        let mut chips = 0u64;
+        for _ in 0..len {
+            chips = (chips << 1) | (state & 1) as u64;
+            let feedback = (state & taps).count_ones() & 1;
+            state = (state >> 1) | (feedback << (degree - 1));
+        }
+        Self::new(chips, len)
+    }
+
+    /// The chips, first to last.
+    pub fn chips(&self) -> impl ExactSizeIterator<Item = bool> {
+        let chips = self.chips;
+        (0..self.len).rev().map(move |i| (chips >> i) & 1 == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Correlation of `word` with itself shifted by `shift` chips, cyclically or not.
+    fn correlation(word: &[bool], shift: usize, cyclic: bool) -> i32 {
+        let len = word.len();
+        (0..len)
+            .filter(|&i| cyclic || i + shift < len)
+            .map(|i| if word[i] == word[(i + shift) % len] { 1 } else { -1 })
+            .sum()
+    }
+
+    #[test]
+    fn sync_words_have_low_sidelobes() {
+        let barker: Vec<bool> = SyncWord::BARKER_13.chips().collect();
+        assert_eq!(barker.len(), 13);
+        assert_eq!(correlation(&barker, 0, false), 13);
+        assert!((1..13).all(|shift| correlation(&barker, shift, false).abs() <= 1));
+
+        // Maximal-length sequences: -1 at every cyclic shift
+        for degree in 2..=6 {
+            let pn: Vec<bool> = SyncWord::pn(degree).unwrap().chips().collect();
+            assert_eq!(pn.len(), (1 << degree) - 1);
+            assert!((1..pn.len()).all(|shift| correlation(&pn, shift, true) == -1), "degree {}", degree);
+        }
+        assert!(SyncWord::pn(7).is_err());
+        assert!(SyncWord::new(0b100, 2).is_err());
+    }
+}
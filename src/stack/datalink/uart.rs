@@ -0,0 +1,149 @@
+// * Asynchronous (UART) character framing: start bit, data bits, parity and stop bits
+
+/// Number of stop bits closing every character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    /// One and a half bit periods, as used by 45.45 baud RTTY. Only binary modems with a
+    /// stream modulator (FSK, OOK) can end a symbol halfway; other modems round it up to two.
+    OneAndHalf,
+    Two,
+}
+
+impl StopBits {
+    /// Length in half bit periods.
+    pub(crate) fn half_bits(self) -> usize {
+        match self {
+            StopBits::One => 2,
+            StopBits::OneAndHalf => 3,
+            StopBits::Two => 4,
+        }
+    }
+}
+
+/// Parity bit sent after the data bits, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    /// Makes the number of 1s in the data and parity bits even.
+    Even,
+    /// Makes the number of 1s in the data and parity bits odd.
+    Odd,
+    /// Always 1.
+    Mark,
+    /// Always 0.
+    Space,
+}
+
+/// Order the data bits of a character go out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Least significant bit first, as UARTs send them.
+    LsbFirst,
+    /// Most significant bit first, like [`byte_to_bits`](crate::modem::byte_to_bits).
+    MsbFirst,
+}
+
+/// How bytes go out as asynchronous characters: a start bit (space), the data bits, the
+/// parity bit and the stop bits (marks).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UartFraming {
+    pub data_bits: usize, // 5 to 8; higher bits of a byte are not sent
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub bit_order: BitOrder,
+}
+
+impl Default for UartFraming {
+    /// 8N1, LSB first.
+    fn default() -> Self {
+        Self { data_bits: 8, parity: Parity::None, stop_bits: StopBits::One, bit_order: BitOrder::LsbFirst }
+    }
+}
+
+impl UartFraming {
+    /// Bits every character is checked on: the start bit, the data bits, the parity bit
+    /// and one stop bit.
+    pub fn bits_per_character(&self) -> usize {
+        self.data_bits + (self.parity != Parity::None) as usize + 2
+    }
+
+    /// The parity bit of `data`, if any.
+    fn parity_bit(&self, data: &[bool]) -> Option<bool> {
+        let odd_ones = data.iter().filter(|&&bit| bit).count() % 2 == 1;
+        match self.parity {
+            Parity::None => None,
+            Parity::Even => Some(odd_ones),
+            Parity::Odd => Some(!odd_ones),
+            Parity::Mark => Some(true),
+            Parity::Space => Some(false),
+        }
+    }
+
+    /// Frames `byte`: the start bit, the data bits, the parity bit and one stop bit.
+    pub fn frame(&self, byte: u8) -> Vec<bool> {
+        let data: Vec<bool> = (0..self.data_bits)
+            .map(|i| match self.bit_order {
+                BitOrder::LsbFirst => i,
+                BitOrder::MsbFirst => self.data_bits - 1 - i,
+            })
+            .map(|bit| (byte >> bit) & 1 == 1)
+            .collect();
+        let mut bits = vec![false];
+        bits.extend(&data);
+        bits.extend(self.parity_bit(&data));
+        bits.push(true);
+        bits
+    }
+
+    /// Reads a character laid out like [`frame`](Self::frame) makes them, returning the byte
+    /// and whether its parity holds. `None` if the start or stop bit is wrong.
+    pub fn deframe(&self, bits: &[bool]) -> Option<(u8, bool)> {
+        if bits.len() < self.bits_per_character() || bits[0] || !bits[self.bits_per_character() - 1] {
+            return None;
+        }
+        let data = &bits[1..=self.data_bits];
+        let byte = data.iter().enumerate().fold(0u8, |byte, (i, &bit)| {
+            let position = match self.bit_order {
+                BitOrder::LsbFirst => i,
+                BitOrder::MsbFirst => self.data_bits - 1 - i,
+            };
+            byte | (bit as u8) << position
+        });
+        let parity_holds = self.parity_bit(data).is_none_or(|parity| parity == bits[self.data_bits + 1]);
+        Some((byte, parity_holds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framing_places_parity_and_orders_bits() {
+        let seven_e1 = UartFraming { data_bits: 7, parity: Parity::Even, ..UartFraming::default() };
+        // 'C' = 0x43 = 100_0011: three 1s, so even parity adds a fourth
+        let bits = [false, true, true, false, false, false, false, true, true, true];
+        assert_eq!(seven_e1.frame(b'C'), bits);
+        assert_eq!(seven_e1.deframe(&bits), Some((b'C', true)));
+
+        let mut flipped = bits;
+        flipped[3] = true;
+        assert_eq!(seven_e1.deframe(&flipped), Some((b'G', false)));
+        flipped[0] = true;
+        assert_eq!(seven_e1.deframe(&flipped), None);
+
+        for parity in [Parity::None, Parity::Even, Parity::Odd, Parity::Mark, Parity::Space] {
+            for bit_order in [BitOrder::LsbFirst, BitOrder::MsbFirst] {
+                let framing = UartFraming { data_bits: 6, parity, bit_order, ..UartFraming::default() };
+                for byte in 0..64 {
+                    assert_eq!(framing.deframe(&framing.frame(byte)), Some((byte, true)));
+                }
+            }
+        }
+
+        // MSB first agrees with `byte_to_bits`
+        let msb_first = UartFraming { bit_order: BitOrder::MsbFirst, ..UartFraming::default() };
+        assert_eq!(msb_first.frame(0xA5)[1..9], crate::modem::byte_to_bits::<bool>(0xA5)[..]);
+    }
+}